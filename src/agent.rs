@@ -0,0 +1,199 @@
+use crate::ai::expectimax::Expectimax;
+use crate::ai::mcts::Mcts;
+use crate::ai::Evaluator;
+use crate::board::{self, Direction};
+use crate::game::Game;
+use crate::random::{Pcg32, RandomNumberGenerator};
+use crate::tile_generator::TileOption;
+use crate::tournament::check_config;
+
+pub struct GameView<'a> {
+    pub cells: Vec<Vec<i32>>,
+    pub score: i32,
+    pub moves: usize,
+    pub legal_moves: Vec<Direction>,
+    pub tile_options: &'a [TileOption],
+}
+
+impl<'a> GameView<'a> {
+    /// Agents look ahead with the classic `board` functions, so views are
+    /// only made of classic-rule games that slide tiles on boards without
+    /// walls or a mask.
+    pub fn new<R: RandomNumberGenerator + Clone>(
+        game: &'a Game<R>,
+    ) -> Result<GameView<'a>, String> {
+        check_config(game.config())?;
+        Ok(GameView {
+            cells: game.cells().to_vec(),
+            score: game.score(),
+            moves: game.moves(),
+            legal_moves: game.legal_moves(),
+            tile_options: &game.config().tile_options,
+        })
+    }
+}
+
+/// A player. `choose_move` is only called while `state.legal_moves` is not
+/// empty, and must return one of those moves.
+pub trait Agent {
+    fn choose_move(&mut self, state: &GameView) -> Direction;
+}
+
+pub struct RandomAgent {
+    rng: Pcg32,
+}
+
+impl RandomAgent {
+    pub fn new(seed: u64) -> RandomAgent {
+        RandomAgent {
+            rng: Pcg32::new(seed),
+        }
+    }
+}
+
+impl Agent for RandomAgent {
+    fn choose_move(&mut self, state: &GameView) -> Direction {
+        let index = self.rng.next_in_range(0..state.legal_moves.len() as i32);
+        state.legal_moves[index as usize]
+    }
+}
+
+/// Takes the move with the largest immediate merge score, breaking ties by
+/// the number of empty cells left afterwards.
+pub struct GreedyAgent;
+
+impl Agent for GreedyAgent {
+    fn choose_move(&mut self, state: &GameView) -> Direction {
+        let mut best = (state.legal_moves[0], -1, 0);
+        for direction in &state.legal_moves {
+            let (cells, score) = board::shift_board(&state.cells, *direction);
+            let empty = board::select_empty_cells(&cells).len();
+            if (score, empty) > (best.1, best.2) {
+                best = (*direction, score, empty);
+            }
+        }
+        best.0
+    }
+}
+
+/// Keeps the largest tile in the bottom left corner by preferring Down, then
+/// Left, then Right, and only moving Up when nothing else is possible.
+pub struct CornerAgent;
+
+impl Agent for CornerAgent {
+    fn choose_move(&mut self, state: &GameView) -> Direction {
+        [
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+            Direction::Up,
+        ]
+        .iter()
+        .copied()
+        .find(|direction| state.legal_moves.contains(direction))
+        .unwrap_or(state.legal_moves[0])
+    }
+}
+
+impl<E: Evaluator> Agent for Expectimax<E> {
+    fn choose_move(&mut self, state: &GameView) -> Direction {
+        self.best_move(&state.cells)
+            .map_or(state.legal_moves[0], |(direction, _)| direction)
+    }
+}
+
+impl<E: Evaluator> Agent for Mcts<E> {
+    fn choose_move(&mut self, state: &GameView) -> Direction {
+        self.best_move(&state.cells).unwrap_or(state.legal_moves[0])
+    }
+}
+
+#[cfg(test)]
+fn view<'a>(cells: &'a [Vec<i32>], tile_options: &'a [TileOption]) -> GameView<'a> {
+    GameView {
+        cells: cells.to_vec(),
+        score: 0,
+        moves: 0,
+        legal_moves: board::legal_moves(cells),
+        tile_options,
+    }
+}
+
+#[test]
+fn test_game_view() {
+    use crate::board::Movement;
+    use crate::game::GameConfig;
+
+    let game = Game::new(GameConfig::default(), Pcg32::new(1)).unwrap();
+    let state = GameView::new(&game).unwrap();
+    assert_eq!(game.cells(), &state.cells);
+    assert_eq!(state.legal_moves, game.legal_moves());
+    assert_eq!(state.tile_options.len(), 2);
+
+    let walled = GameConfig {
+        walls: vec![(1, 1)],
+        ..GameConfig::default()
+    };
+    let stepping = GameConfig {
+        movement: Movement::Step,
+        ..GameConfig::default()
+    };
+    for config in [walled, stepping] {
+        let game = Game::new(config, Pcg32::new(1)).unwrap();
+        assert!(GameView::new(&game).is_err());
+    }
+}
+
+#[test]
+fn test_random_agent_picks_legal_moves() {
+    let cells = vec![vec![2, 0], vec![4, 0]];
+    let state = view(&cells, &[]);
+    let mut agent = RandomAgent::new(3);
+    for _ in 0..20 {
+        assert!(state.legal_moves.contains(&agent.choose_move(&state)));
+    }
+}
+
+#[test]
+fn test_greedy_agent() {
+    let cells = vec![vec![8, 2, 0], vec![8, 4, 0], vec![0, 0, 0]];
+    assert_eq!(GreedyAgent.choose_move(&view(&cells, &[])), Direction::Up);
+
+    let cells = vec![
+        vec![2, 4, 4, 0],
+        vec![2, 0, 0, 0],
+        vec![2, 0, 0, 0],
+        vec![2, 0, 0, 0],
+    ];
+    assert_eq!(GreedyAgent.choose_move(&view(&cells, &[])), Direction::Up);
+}
+
+#[test]
+fn test_corner_agent() {
+    let cells = vec![vec![2, 0], vec![4, 0]];
+    assert_eq!(
+        CornerAgent.choose_move(&view(&cells, &[])),
+        Direction::Right
+    );
+
+    let cells = vec![vec![2, 0], vec![0, 0]];
+    assert_eq!(CornerAgent.choose_move(&view(&cells, &[])), Direction::Down);
+}
+
+#[test]
+fn test_search_agents() {
+    use crate::ai::heuristics::Heuristic;
+    use crate::ai::mcts::{Budget, Rollout};
+
+    let options = crate::game::GameConfig::default().tile_options;
+    let cells = vec![vec![2, 2, 8], vec![0, 0, 0], vec![0, 0, 0]];
+    let state = view(&cells, &options);
+
+    let mut expectimax = Expectimax::new(options.clone(), Heuristic::default());
+    expectimax.depth = 1;
+    assert!(state.legal_moves.contains(&expectimax.choose_move(&state)));
+
+    let mut mcts = Mcts::new(options.clone(), Rollout::<Heuristic>::Random, 1).unwrap();
+    mcts.budget = Budget::Iterations(50);
+    assert!(state.legal_moves.contains(&mcts.choose_move(&state)));
+}
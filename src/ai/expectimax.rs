@@ -0,0 +1,214 @@
+use crate::ai::Evaluator;
+use crate::board::{self, Direction};
+use crate::tile_generator::TileOption;
+
+const TOTAL_PROBABILITY: f64 = 100.0;
+
+/// Expectimax search that alternates max nodes over the four directions with
+/// chance nodes over every empty cell and tile option. Chance branches whose
+/// probability of being reached drops below `probability_cutoff` are scored
+/// with the evaluator instead of being expanded further.
+pub struct Expectimax<E: Evaluator> {
+    pub depth: usize,
+    pub probability_cutoff: f64,
+    evaluator: E,
+    tile_options: Vec<TileOption>,
+}
+
+impl<E: Evaluator> Expectimax<E> {
+    pub fn new(tile_options: Vec<TileOption>, evaluator: E) -> Expectimax<E> {
+        Expectimax {
+            depth: 3,
+            probability_cutoff: 0.0001,
+            evaluator,
+            tile_options,
+        }
+    }
+
+    pub fn evaluator(&self) -> &E {
+        &self.evaluator
+    }
+
+    pub fn best_move(&self, cells: &[Vec<i32>]) -> Option<(Direction, f64)> {
+        self.move_values(cells)
+            .into_iter()
+            .fold(None, |best, (direction, value)| match best {
+                Some((_, best_value)) if best_value >= value => best,
+                _ => Some((direction, value)),
+            })
+    }
+
+    pub fn move_values(&self, cells: &[Vec<i32>]) -> Vec<(Direction, f64)> {
+        board::legal_moves(cells)
+            .into_iter()
+            .map(|direction| {
+                let (next, _) = board::shift_board(cells, direction);
+                (direction, self.chance_value(&next, self.depth.max(1), 1.0))
+            })
+            .collect()
+    }
+
+    fn max_value(&self, cells: &[Vec<i32>], depth: usize, probability: f64) -> f64 {
+        if depth == 0 {
+            return self.evaluator.evaluate(cells);
+        }
+        board::legal_moves(cells)
+            .into_iter()
+            .map(|direction| {
+                let (next, _) = board::shift_board(cells, direction);
+                self.chance_value(&next, depth, probability)
+            })
+            .fold(None, |best: Option<f64>, value| {
+                Some(best.map_or(value, |best| best.max(value)))
+            })
+            .unwrap_or_else(|| self.evaluator.evaluate(cells))
+    }
+
+    fn chance_value(&self, cells: &[Vec<i32>], depth: usize, probability: f64) -> f64 {
+        let empty_cells = board::select_empty_cells(cells);
+        if empty_cells.is_empty() || probability < self.probability_cutoff {
+            return self.evaluator.evaluate(cells);
+        }
+        let cell_probability = 1.0 / empty_cells.len() as f64;
+        let mut next = cells.to_vec();
+        let mut value = 0.0;
+        for (i, j) in empty_cells {
+            for option in &self.tile_options {
+                let option_probability =
+                    cell_probability * option.probability as f64 / TOTAL_PROBABILITY;
+                if option_probability == 0.0 {
+                    continue;
+                }
+                next[i][j] = option.value;
+                value += option_probability
+                    * self.max_value(&next, depth - 1, probability * option_probability);
+            }
+            next[i][j] = 0;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+fn classic_options() -> Vec<TileOption> {
+    crate::game::GameConfig::default().tile_options
+}
+
+#[test]
+fn test_best_move_takes_the_merge() {
+    let empty_cells =
+        |cells: &[Vec<i32>]| cells.iter().flatten().filter(|cell| **cell == 0).count() as f64;
+    let expectimax = Expectimax {
+        depth: 1,
+        ..Expectimax::new(classic_options(), empty_cells)
+    };
+    let cells = vec![
+        vec![2, 4, 8, 16],
+        vec![4, 8, 16, 32],
+        vec![8, 16, 32, 64],
+        vec![16, 32, 64, 64],
+    ];
+    let (direction, value) = expectimax.best_move(&cells).unwrap();
+    assert!(direction == Direction::Left || direction == Direction::Right);
+    assert_eq!(value, 0.0);
+
+    let cells = vec![
+        vec![2, 2, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+    ];
+    let (direction, value) = expectimax.best_move(&cells).unwrap();
+    assert_eq!(direction, Direction::Left);
+    assert!((value - 14.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_no_legal_moves() {
+    let expectimax = Expectimax::new(classic_options(), |_: &[Vec<i32>]| 0.0);
+    let cells = vec![vec![2, 4], vec![4, 2]];
+    assert_eq!(expectimax.best_move(&cells), None);
+    assert!(expectimax.move_values(&cells).is_empty());
+}
+
+#[test]
+fn test_chance_nodes_use_tile_weights() {
+    let max_tile = |cells: &[Vec<i32>]| *cells.iter().flatten().max().unwrap() as f64;
+    let options = vec![
+        TileOption {
+            value: 2,
+            probability: 75,
+        },
+        TileOption {
+            value: 8,
+            probability: 25,
+        },
+    ];
+    let expectimax = Expectimax {
+        depth: 1,
+        ..Expectimax::new(options, max_tile)
+    };
+    let cells = vec![vec![0, 4], vec![2, 4]];
+    let values = expectimax.move_values(&cells);
+    let up = values
+        .iter()
+        .find(|(direction, _)| *direction == Direction::Up)
+        .unwrap();
+    assert_eq!(up.1, 0.75 * 8.0 + 0.25 * 8.0);
+    let left = values
+        .iter()
+        .find(|(direction, _)| *direction == Direction::Left)
+        .unwrap();
+    assert_eq!(left.1, 0.75 * 4.0 + 0.25 * 8.0);
+}
+
+#[test]
+fn test_probability_cutoff_stops_expansion() {
+    use std::cell::Cell;
+
+    let calls = Cell::new(0);
+    let counting = |_: &[Vec<i32>]| {
+        calls.set(calls.get() + 1);
+        0.0
+    };
+    let cells = vec![
+        vec![2, 0, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 4],
+    ];
+    let pruned = Expectimax {
+        depth: 2,
+        probability_cutoff: 0.5,
+        ..Expectimax::new(classic_options(), &counting)
+    };
+    pruned.best_move(&cells);
+    let pruned_calls = calls.get();
+
+    calls.set(0);
+    let full = Expectimax {
+        depth: 2,
+        probability_cutoff: 0.0,
+        ..Expectimax::new(classic_options(), &counting)
+    };
+    full.best_move(&cells);
+    assert!(pruned_calls * 10 < calls.get());
+}
+
+#[test]
+fn test_heuristic_player_reaches_256() {
+    use crate::ai::heuristics::Heuristic;
+    use crate::game::{Game, GameConfig};
+    use crate::random::Pcg32;
+
+    let mut game = Game::new(GameConfig::default(), Pcg32::new(1)).unwrap();
+    let expectimax = Expectimax {
+        depth: 1,
+        ..Expectimax::new(classic_options(), Heuristic::default())
+    };
+    while !game.is_over() && game.max_tile() < 256 {
+        let (direction, _) = expectimax.best_move(&game.cells().to_vec()).unwrap();
+        game.make_move(direction);
+    }
+    assert!(game.max_tile() >= 256);
+}
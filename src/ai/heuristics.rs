@@ -0,0 +1,120 @@
+use crate::ai::Evaluator;
+
+/// Weighted sum of the usual hand-tuned 2048 features, computed on tile
+/// ranks (`log2` of the tile value) over every row and column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heuristic {
+    pub empty_weight: f64,
+    pub merge_weight: f64,
+    pub monotonicity_weight: f64,
+    pub smoothness_weight: f64,
+    pub corner_weight: f64,
+}
+
+impl Default for Heuristic {
+    fn default() -> Heuristic {
+        Heuristic {
+            empty_weight: 2.7,
+            merge_weight: 1.0,
+            monotonicity_weight: 1.0,
+            smoothness_weight: 0.1,
+            corner_weight: 1.0,
+        }
+    }
+}
+
+impl Evaluator for Heuristic {
+    fn evaluate(&self, cells: &[Vec<i32>]) -> f64 {
+        let ranks: Vec<Vec<f64>> = cells
+            .iter()
+            .map(|row| row.iter().map(|cell| rank(*cell)).collect())
+            .collect();
+        let columns: Vec<Vec<f64>> = (0..ranks[0].len())
+            .map(|j| ranks.iter().map(|row| row[j]).collect())
+            .collect();
+
+        let empty = cells.iter().flatten().filter(|cell| **cell == 0).count() as f64;
+        let mut merges = 0.0;
+        let mut monotonicity = 0.0;
+        let mut smoothness = 0.0;
+        for line in ranks.iter().chain(columns.iter()) {
+            let tiles: Vec<f64> = line.iter().copied().filter(|r| *r > 0.0).collect();
+            for pair in tiles.windows(2) {
+                if pair[0] == pair[1] {
+                    merges += 1.0;
+                }
+                smoothness -= (pair[0] - pair[1]).abs();
+            }
+            let (mut increasing, mut decreasing) = (0.0, 0.0);
+            for pair in line.windows(2) {
+                if pair[0] > pair[1] {
+                    decreasing += pair[0] - pair[1];
+                } else {
+                    increasing += pair[1] - pair[0];
+                }
+            }
+            monotonicity -= f64::min(increasing, decreasing);
+        }
+        let max_rank = ranks.iter().flatten().copied().fold(0.0, f64::max);
+        let last_row = ranks.len() - 1;
+        let last_col = ranks[0].len() - 1;
+        let corner = [
+            ranks[0][0],
+            ranks[0][last_col],
+            ranks[last_row][0],
+            ranks[last_row][last_col],
+        ]
+        .contains(&max_rank);
+
+        self.empty_weight * empty
+            + self.merge_weight * merges
+            + self.monotonicity_weight * monotonicity
+            + self.smoothness_weight * smoothness
+            + if corner {
+                self.corner_weight * max_rank
+            } else {
+                0.0
+            }
+    }
+}
+
+pub fn rank(cell: i32) -> f64 {
+    if cell <= 0 {
+        0.0
+    } else {
+        (cell as f64).log2()
+    }
+}
+
+#[test]
+fn test_rank() {
+    assert_eq!(rank(0), 0.0);
+    assert_eq!(rank(2), 1.0);
+    assert_eq!(rank(2048), 11.0);
+}
+
+#[test]
+fn test_prefers_ordered_boards() {
+    let heuristic = Heuristic::default();
+    let ordered = vec![
+        vec![256, 128, 64, 32],
+        vec![16, 8, 4, 2],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+    ];
+    let scattered = vec![
+        vec![2, 64, 0, 16],
+        vec![128, 0, 4, 0],
+        vec![0, 8, 0, 256],
+        vec![32, 0, 0, 0],
+    ];
+    assert!(heuristic.evaluate(&ordered) > heuristic.evaluate(&scattered));
+}
+
+#[test]
+fn test_prefers_empty_cells() {
+    let heuristic = Heuristic::default();
+    let open = vec![vec![4, 0, 0], vec![0, 0, 0], vec![0, 0, 0]];
+    let crowded = vec![vec![2, 2, 0], vec![0, 0, 0], vec![0, 0, 0]];
+    assert!(heuristic.evaluate(&open) > heuristic.evaluate(&crowded));
+}
@@ -0,0 +1,356 @@
+use crate::ai::Evaluator;
+use crate::board::{self, Direction};
+use crate::random::{Pcg32, RandomNumberGenerator};
+use crate::tile_generator::{TileGenerator, TileOption};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Budget {
+    Iterations(usize),
+    Time(Duration),
+}
+
+pub enum Rollout<E: Evaluator> {
+    Random,
+    Heuristic(E),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveStats {
+    pub direction: Direction,
+    pub visits: usize,
+    pub value: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<Direction>,
+    pub stats: Vec<MoveStats>,
+    pub iterations: usize,
+}
+
+struct DecisionNode {
+    cells: Vec<Vec<i32>>,
+    children: Option<Vec<(Direction, usize)>>,
+}
+
+struct ChanceNode {
+    cells: Vec<Vec<i32>>,
+    reward: f64,
+    visits: usize,
+    total_value: f64,
+    children: Vec<((usize, usize, i32), usize)>,
+}
+
+/// Monte Carlo tree search where every move leads to a chance node whose
+/// outcomes are sampled from a `TileGenerator`. Values are the merge score
+/// collected from that move onwards, including the rollout.
+pub struct Mcts<E: Evaluator> {
+    pub budget: Budget,
+    pub rollout_depth: usize,
+    pub exploration: f64,
+    rollout: Rollout<E>,
+    generator: TileGenerator<Pcg32>,
+    rng: Pcg32,
+}
+
+impl<E: Evaluator> Mcts<E> {
+    pub fn new(
+        tile_options: Vec<TileOption>,
+        rollout: Rollout<E>,
+        seed: u64,
+    ) -> Result<Mcts<E>, String> {
+        Ok(Mcts {
+            budget: Budget::Iterations(1000),
+            rollout_depth: 20,
+            exploration: 1.0,
+            rollout,
+            generator: TileGenerator::new(tile_options, Pcg32::new(seed))?,
+            rng: Pcg32::with_stream(seed, 1),
+        })
+    }
+
+    pub fn best_move(&mut self, cells: &[Vec<i32>]) -> Option<Direction> {
+        self.search(cells).best_move
+    }
+
+    pub fn search(&mut self, cells: &[Vec<i32>]) -> SearchResult {
+        let mut decisions = vec![DecisionNode {
+            cells: cells.to_vec(),
+            children: None,
+        }];
+        let mut chances: Vec<ChanceNode> = vec![];
+        let start = Instant::now();
+        let mut iterations = 0;
+        while !self.budget_exhausted(iterations, start) {
+            self.iterate(&mut decisions, &mut chances);
+            iterations += 1;
+            if decisions[0].children.as_ref().is_some_and(|c| c.is_empty()) {
+                break;
+            }
+        }
+
+        let stats: Vec<MoveStats> = decisions[0]
+            .children
+            .iter()
+            .flatten()
+            .map(|(direction, index)| {
+                let chance = &chances[*index];
+                MoveStats {
+                    direction: *direction,
+                    visits: chance.visits,
+                    value: if chance.visits == 0 {
+                        0.0
+                    } else {
+                        chance.total_value / chance.visits as f64
+                    },
+                }
+            })
+            .collect();
+        let best_move = stats
+            .iter()
+            .fold(None, |best: Option<&MoveStats>, stat| match best {
+                Some(best) if (best.visits, best.value) >= (stat.visits, stat.value) => Some(best),
+                _ => Some(stat),
+            })
+            .map(|stat| stat.direction);
+        SearchResult {
+            best_move,
+            stats,
+            iterations,
+        }
+    }
+
+    fn budget_exhausted(&self, iterations: usize, start: Instant) -> bool {
+        match self.budget {
+            Budget::Iterations(limit) => iterations >= limit,
+            Budget::Time(limit) => start.elapsed() >= limit,
+        }
+    }
+
+    fn iterate(&mut self, decisions: &mut Vec<DecisionNode>, chances: &mut Vec<ChanceNode>) {
+        let mut path = vec![];
+        let mut decision = 0;
+        let leaf_value = loop {
+            if decisions[decision].children.is_none() {
+                let children = board::legal_moves(&decisions[decision].cells)
+                    .into_iter()
+                    .map(|direction| {
+                        let (cells, reward) =
+                            board::shift_board(&decisions[decision].cells, direction);
+                        chances.push(ChanceNode {
+                            cells,
+                            reward: reward as f64,
+                            visits: 0,
+                            total_value: 0.0,
+                            children: vec![],
+                        });
+                        (direction, chances.len() - 1)
+                    })
+                    .collect();
+                decisions[decision].children = Some(children);
+            }
+            let chance = match self.select(&decisions[decision], chances) {
+                Some(chance) => chance,
+                None => break 0.0,
+            };
+            path.push(chance);
+            let first_visit = chances[chance].visits == 0;
+            let mut cells = chances[chance].cells.clone();
+            let empty_cells = board::select_empty_cells(&cells);
+            let spawn = self.generator.generate_tile(&empty_cells);
+            cells[spawn.0][spawn.1] = spawn.2;
+            if first_visit {
+                break self.rollout(cells);
+            }
+            decision = match chances[chance]
+                .children
+                .iter()
+                .find(|(outcome, _)| *outcome == spawn)
+            {
+                Some((_, child)) => *child,
+                None => {
+                    decisions.push(DecisionNode {
+                        cells,
+                        children: None,
+                    });
+                    chances[chance].children.push((spawn, decisions.len() - 1));
+                    decisions.len() - 1
+                }
+            };
+        };
+
+        let mut value = leaf_value;
+        for chance in path.into_iter().rev() {
+            let node = &mut chances[chance];
+            value += node.reward;
+            node.visits += 1;
+            node.total_value += value;
+        }
+    }
+
+    fn select(&self, decision: &DecisionNode, chances: &[ChanceNode]) -> Option<usize> {
+        let children = decision.children.as_ref()?;
+        if let Some((_, unvisited)) = children.iter().find(|(_, c)| chances[*c].visits == 0) {
+            return Some(*unvisited);
+        }
+        let total_visits: usize = children.iter().map(|(_, c)| chances[*c].visits).sum();
+        let mean = |c: usize| chances[c].total_value / chances[c].visits as f64;
+        let scale = children.iter().map(|(_, c)| mean(*c)).fold(1.0, f64::max);
+        children
+            .iter()
+            .map(|(_, c)| {
+                let exploration = (2.0 * (total_visits as f64).ln() / chances[*c].visits as f64)
+                    .sqrt()
+                    * self.exploration;
+                (*c, mean(*c) / scale + exploration)
+            })
+            .fold(None, |best: Option<(usize, f64)>, (c, uct)| match best {
+                Some((_, best_uct)) if best_uct >= uct => best,
+                _ => Some((c, uct)),
+            })
+            .map(|(c, _)| c)
+    }
+
+    fn rollout(&mut self, mut cells: Vec<Vec<i32>>) -> f64 {
+        let mut score = 0;
+        for _ in 0..self.rollout_depth {
+            let moves = board::legal_moves(&cells);
+            if moves.is_empty() {
+                break;
+            }
+            let (next, reward) = match &self.rollout {
+                Rollout::Random => {
+                    let direction = moves[self.rng.next_in_range(0..moves.len() as i32) as usize];
+                    board::shift_board(&cells, direction)
+                }
+                Rollout::Heuristic(evaluator) => moves
+                    .iter()
+                    .map(|direction| board::shift_board(&cells, *direction))
+                    .map(|(next, reward)| {
+                        let value = evaluator.evaluate(&next);
+                        (next, reward, value)
+                    })
+                    .fold(
+                        None,
+                        |best: Option<(Vec<Vec<i32>>, i32, f64)>, candidate| match best {
+                            Some(best) if best.2 >= candidate.2 => Some(best),
+                            _ => Some(candidate),
+                        },
+                    )
+                    .map(|(next, reward, _)| (next, reward))
+                    .unwrap(),
+            };
+            score += reward;
+            cells = next;
+            board::create_random_tile(&mut cells, &mut self.generator);
+        }
+        score as f64
+    }
+}
+
+#[cfg(test)]
+fn random_mcts(iterations: usize, seed: u64) -> Mcts<crate::ai::heuristics::Heuristic> {
+    let mut mcts = Mcts::new(
+        crate::game::GameConfig::default().tile_options,
+        Rollout::Random,
+        seed,
+    )
+    .unwrap();
+    mcts.budget = Budget::Iterations(iterations);
+    mcts
+}
+
+#[test]
+fn test_iteration_budget() {
+    let mut mcts = random_mcts(200, 1);
+    let cells = vec![
+        vec![2, 0, 0, 0],
+        vec![0, 4, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 2, 0],
+    ];
+    let result = mcts.search(&cells);
+    assert_eq!(result.iterations, 200);
+    assert_eq!(result.stats.len(), 4);
+    assert_eq!(
+        result.stats.iter().map(|stat| stat.visits).sum::<usize>(),
+        200
+    );
+    assert!(result.stats.iter().all(|stat| stat.visits > 0));
+}
+
+#[test]
+fn test_time_budget() {
+    let mut mcts = random_mcts(0, 1);
+    mcts.budget = Budget::Time(Duration::from_millis(20));
+    let start = Instant::now();
+    let result = mcts.search(&[vec![2, 0], vec![0, 0]]);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(result.iterations > 0);
+    assert!(result.best_move.is_some());
+}
+
+#[test]
+fn test_finds_the_big_merge() {
+    let mut mcts = random_mcts(300, 3);
+    let cells = vec![
+        vec![2, 4, 8, 16],
+        vec![32, 64, 128, 256],
+        vec![4, 8, 16, 32],
+        vec![1024, 1024, 2, 4],
+    ];
+    let result = mcts.search(&cells);
+    let best = result.best_move.unwrap();
+    assert!(best == Direction::Left || best == Direction::Right);
+    let best_stats = result
+        .stats
+        .iter()
+        .find(|stat| stat.direction == best)
+        .unwrap();
+    assert!(best_stats.value >= 2048.0);
+}
+
+#[test]
+fn test_no_legal_moves() {
+    let mut mcts = random_mcts(50, 1);
+    let result = mcts.search(&[vec![2, 4], vec![4, 2]]);
+    assert_eq!(result.best_move, None);
+    assert!(result.stats.is_empty());
+}
+
+#[test]
+fn test_same_seed_same_result() {
+    let cells = vec![
+        vec![2, 2, 0, 4],
+        vec![0, 4, 8, 0],
+        vec![0, 0, 2, 0],
+        vec![16, 0, 0, 2],
+    ];
+    let first = random_mcts(100, 42).search(&cells);
+    let second = random_mcts(100, 42).search(&cells);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn test_heuristic_rollout() {
+    use crate::ai::heuristics::Heuristic;
+
+    let mut mcts = Mcts::new(
+        crate::game::GameConfig::default().tile_options,
+        Rollout::Heuristic(Heuristic::default()),
+        5,
+    )
+    .unwrap();
+    mcts.budget = Budget::Iterations(50);
+    mcts.rollout_depth = 5;
+    let cells = vec![
+        vec![0, 0, 0, 0],
+        vec![0, 2, 0, 0],
+        vec![0, 0, 2, 0],
+        vec![0, 0, 0, 0],
+    ];
+    let result = mcts.search(&cells);
+    assert_eq!(result.iterations, 50);
+    assert!(result.best_move.is_some());
+}
@@ -0,0 +1,17 @@
+pub mod expectimax;
+
+pub mod mcts;
+
+pub mod ntuple;
+
+pub mod heuristics;
+
+pub trait Evaluator {
+    fn evaluate(&self, cells: &[Vec<i32>]) -> f64;
+}
+
+impl<F: Fn(&[Vec<i32>]) -> f64> Evaluator for F {
+    fn evaluate(&self, cells: &[Vec<i32>]) -> f64 {
+        self(cells)
+    }
+}
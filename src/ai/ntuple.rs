@@ -0,0 +1,516 @@
+use crate::ai::Evaluator;
+use crate::board::{self, Direction};
+use crate::game::{Game, GameConfig, MoveOutcome};
+use crate::random::Pcg32;
+use crate::tournament::check_config;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+const WEIGHTS_MAGIC: &[u8; 4] = b"NTUP";
+const WEIGHTS_VERSION: u32 = 1;
+const MAX_TUPLE_WEIGHTS: usize = 1 << 28;
+
+/// An n-tuple network over tile ranks. Every tuple is sampled in all of its
+/// symmetric positions on the board (eight on square boards, four on
+/// rectangular ones) and all of them share the tuple's weight table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NTupleNetwork {
+    rows: usize,
+    cols: usize,
+    base: usize,
+    tuples: Vec<Vec<(usize, usize)>>,
+    weights: Vec<Vec<f32>>,
+    samples: Vec<Vec<Vec<(usize, usize)>>>,
+}
+
+impl NTupleNetwork {
+    pub fn new(
+        rows: usize,
+        cols: usize,
+        base: usize,
+        tuples: Vec<Vec<(usize, usize)>>,
+    ) -> Result<NTupleNetwork, String> {
+        if base < 2 {
+            return Err(format!(
+                "Rank base should be at least 2. Actual base: {}",
+                base
+            ));
+        }
+        let mut weights = vec![];
+        for tuple in &tuples {
+            if tuple.is_empty() {
+                return Err("Tuples should contain at least one cell".to_string());
+            }
+            if let Some((i, j)) = tuple.iter().find(|(i, j)| *i >= rows || *j >= cols) {
+                return Err(format!(
+                    "Tuple cell ({}, {}) is outside of the {}x{} board",
+                    i, j, rows, cols
+                ));
+            }
+            let size = base
+                .checked_pow(tuple.len() as u32)
+                .filter(|size| *size <= MAX_TUPLE_WEIGHTS)
+                .ok_or(format!("Tuple of {} cells is too large", tuple.len()))?;
+            weights.push(vec![0.0; size]);
+        }
+        let samples = tuples
+            .iter()
+            .map(|tuple| symmetries(rows, cols, tuple))
+            .collect();
+        Ok(NTupleNetwork {
+            rows,
+            cols,
+            base,
+            tuples,
+            weights,
+            samples,
+        })
+    }
+
+    /// Rows, columns and the three distinct 2x2 squares of a 4x4 board, with
+    /// ranks up to 32768.
+    pub fn classic_4x4() -> NTupleNetwork {
+        NTupleNetwork::new(
+            4,
+            4,
+            16,
+            vec![
+                vec![(0, 0), (0, 1), (0, 2), (0, 3)],
+                vec![(1, 0), (1, 1), (1, 2), (1, 3)],
+                vec![(0, 0), (0, 1), (1, 0), (1, 1)],
+                vec![(0, 1), (0, 2), (1, 1), (1, 2)],
+                vec![(1, 1), (1, 2), (2, 1), (2, 2)],
+            ],
+        )
+        .unwrap()
+    }
+
+    pub fn tuples(&self) -> &[Vec<(usize, usize)>] {
+        &self.tuples
+    }
+
+    pub fn value(&self, cells: &[Vec<i32>]) -> f64 {
+        let mut value = 0.0;
+        for (samples, weights) in self.samples.iter().zip(self.weights.iter()) {
+            for sample in samples {
+                value += weights[self.index(cells, sample)] as f64;
+            }
+        }
+        value
+    }
+
+    pub fn update(&mut self, cells: &[Vec<i32>], delta: f64) {
+        let features: usize = self.samples.iter().map(|samples| samples.len()).sum();
+        let step = (delta / features as f64) as f32;
+        for tuple in 0..self.samples.len() {
+            for sample in 0..self.samples[tuple].len() {
+                let index = self.index(cells, &self.samples[tuple][sample]);
+                self.weights[tuple][index] += step;
+            }
+        }
+    }
+
+    fn index(&self, cells: &[Vec<i32>], sample: &[(usize, usize)]) -> usize {
+        sample.iter().fold(0, |index, (i, j)| {
+            index * self.base + (tile_rank(cells[*i][*j]) as usize).min(self.base - 1)
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = WEIGHTS_MAGIC.to_vec();
+        for value in &[
+            WEIGHTS_VERSION,
+            self.rows as u32,
+            self.cols as u32,
+            self.base as u32,
+            self.tuples.len() as u32,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for tuple in &self.tuples {
+            bytes.extend_from_slice(&(tuple.len() as u32).to_le_bytes());
+            for (i, j) in tuple {
+                bytes.extend_from_slice(&(*i as u32).to_le_bytes());
+                bytes.extend_from_slice(&(*j as u32).to_le_bytes());
+            }
+        }
+        for weight in self.weights.iter().flatten() {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<NTupleNetwork, String> {
+        if !bytes.starts_with(WEIGHTS_MAGIC) {
+            return Err("Invalid weight file magic bytes".to_string());
+        }
+        let mut position = WEIGHTS_MAGIC.len();
+        let next = |position: &mut usize| -> Result<[u8; 4], String> {
+            let word = bytes
+                .get(*position..*position + 4)
+                .and_then(|chunk| <[u8; 4]>::try_from(chunk).ok())
+                .ok_or_else(|| "Weight file ended unexpectedly".to_string())?;
+            *position += 4;
+            Ok(word)
+        };
+        let version = u32::from_le_bytes(next(&mut position)?);
+        if version != WEIGHTS_VERSION {
+            return Err(format!(
+                "Unsupported weight file version {}. Supported version: {}",
+                version, WEIGHTS_VERSION
+            ));
+        }
+        let rows = u32::from_le_bytes(next(&mut position)?) as usize;
+        let cols = u32::from_le_bytes(next(&mut position)?) as usize;
+        let base = u32::from_le_bytes(next(&mut position)?) as usize;
+        let tuple_count = u32::from_le_bytes(next(&mut position)?);
+        let mut tuples = vec![];
+        for _ in 0..tuple_count {
+            let len = u32::from_le_bytes(next(&mut position)?);
+            let mut tuple = vec![];
+            for _ in 0..len {
+                let i = u32::from_le_bytes(next(&mut position)?) as usize;
+                let j = u32::from_le_bytes(next(&mut position)?) as usize;
+                tuple.push((i, j));
+            }
+            tuples.push(tuple);
+        }
+        // Check the header against the file size before `new` allocates the
+        // weight tables it describes.
+        let total = tuples
+            .iter()
+            .try_fold(0usize, |total, tuple| {
+                u32::try_from(tuple.len())
+                    .ok()
+                    .and_then(|len| base.checked_pow(len))
+                    .and_then(|size| total.checked_add(size))
+            })
+            .and_then(|total| total.checked_mul(4));
+        let remaining = bytes.len() - position;
+        if total != Some(remaining) {
+            return Err(format!(
+                "Weight file should hold the weights of its tuples. Remaining bytes: {}",
+                remaining
+            ));
+        }
+        let mut network = NTupleNetwork::new(rows, cols, base, tuples)?;
+        for weight in network.weights.iter_mut().flatten() {
+            *weight = f32::from_le_bytes(next(&mut position)?);
+        }
+        Ok(network)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_bytes())
+            .map_err(|err| format!("Cannot write weights to {}: {}", path.display(), err))
+    }
+
+    pub fn load(path: &Path) -> Result<NTupleNetwork, String> {
+        let bytes = fs::read(path)
+            .map_err(|err| format!("Cannot read weights from {}: {}", path.display(), err))?;
+        NTupleNetwork::from_bytes(&bytes)
+    }
+}
+
+impl Evaluator for NTupleNetwork {
+    fn evaluate(&self, cells: &[Vec<i32>]) -> f64 {
+        self.value(cells)
+    }
+}
+
+/// Trains a network with TD(0) on afterstates: every move is chosen greedily
+/// by `reward + V(afterstate)`, and the previous afterstate is moved towards
+/// the reward and value of the next one. Like tournaments, trainers only play
+/// sliding games on boards without walls or a mask.
+pub struct Trainer {
+    pub network: NTupleNetwork,
+    pub learning_rate: f64,
+    config: GameConfig,
+    rng: Pcg32,
+}
+
+impl Trainer {
+    pub fn new(network: NTupleNetwork, config: GameConfig, seed: u64) -> Result<Trainer, String> {
+        check_config(&config)?;
+        if config.rows != network.rows || config.cols != network.cols {
+            return Err(format!(
+                "Network is built for {}x{} boards, but the game uses {}x{}",
+                network.rows, network.cols, config.rows, config.cols
+            ));
+        }
+        Ok(Trainer {
+            network,
+            learning_rate: 0.1,
+            config,
+            rng: Pcg32::new(seed),
+        })
+    }
+
+    pub fn train_episode(&mut self) -> Result<i32, String> {
+        let seed = self.rng.next_u32() as u64;
+        let config = GameConfig {
+            endless: true,
+            ..self.config.clone()
+        };
+        let mut game = Game::new(config, Pcg32::new(seed))?;
+        let mut previous: Option<Vec<Vec<i32>>> = None;
+        while let Some((direction, reward, afterstate)) = self.choose_move(&game.cells().to_vec()) {
+            if let Some(previous) = &previous {
+                let error =
+                    reward as f64 + self.network.value(&afterstate) - self.network.value(previous);
+                self.network.update(previous, self.learning_rate * error);
+            }
+            if let MoveOutcome::Moved { .. } = game.make_move(direction) {
+                previous = Some(afterstate);
+            } else {
+                break;
+            }
+        }
+        if let Some(previous) = &previous {
+            let error = -self.network.value(previous);
+            self.network.update(previous, self.learning_rate * error);
+        }
+        Ok(game.score())
+    }
+
+    pub fn train(&mut self, episodes: usize) -> Result<Vec<i32>, String> {
+        (0..episodes).map(|_| self.train_episode()).collect()
+    }
+
+    fn choose_move(&self, cells: &[Vec<i32>]) -> Option<(Direction, i32, Vec<Vec<i32>>)> {
+        board::legal_moves(cells)
+            .into_iter()
+            .map(|direction| {
+                let (afterstate, reward) = board::shift_board(cells, direction);
+                (direction, reward, afterstate)
+            })
+            .fold(
+                None,
+                |best: Option<(Direction, i32, Vec<Vec<i32>>, f64)>, (d, r, a)| {
+                    let value = r as f64 + self.network.value(&a);
+                    match best {
+                        Some(best) if best.3 >= value => Some(best),
+                        _ => Some((d, r, a, value)),
+                    }
+                },
+            )
+            .map(|(direction, reward, afterstate, _)| (direction, reward, afterstate))
+    }
+}
+
+fn tile_rank(cell: i32) -> u32 {
+    if cell <= 0 {
+        0
+    } else {
+        31 - cell.leading_zeros()
+    }
+}
+
+fn symmetries(rows: usize, cols: usize, tuple: &[(usize, usize)]) -> Vec<Vec<(usize, usize)>> {
+    let (last_row, last_col) = (rows - 1, cols - 1);
+    let count = if rows == cols { 8 } else { 4 };
+    (0..count)
+        .map(|symmetry| {
+            tuple
+                .iter()
+                .map(|(i, j)| {
+                    let (i, j) = if symmetry >= 4 { (*j, *i) } else { (*i, *j) };
+                    match symmetry % 4 {
+                        0 => (i, j),
+                        1 => (i, last_col - j),
+                        2 => (last_row - i, j),
+                        _ => (last_row - i, last_col - j),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_new_validates_tuples() {
+    assert!(NTupleNetwork::new(4, 4, 16, vec![vec![(0, 0), (4, 0)]]).is_err());
+    assert!(NTupleNetwork::new(4, 4, 16, vec![vec![]]).is_err());
+    assert!(NTupleNetwork::new(4, 4, 1, vec![vec![(0, 0)]]).is_err());
+    assert!(NTupleNetwork::new(4, 4, 16, vec![vec![(0, 0); 20]]).is_err());
+}
+
+#[test]
+fn test_symmetries() {
+    let square = symmetries(4, 4, &[(0, 0), (0, 1)]);
+    assert_eq!(square.len(), 8);
+    assert!(square.contains(&vec![(0, 0), (1, 0)]));
+    assert!(square.contains(&vec![(3, 3), (3, 2)]));
+    assert!(square.contains(&vec![(0, 3), (1, 3)]));
+
+    let rectangle = symmetries(2, 3, &[(0, 0), (0, 1)]);
+    assert_eq!(
+        rectangle,
+        vec![
+            vec![(0, 0), (0, 1)],
+            vec![(0, 2), (0, 1)],
+            vec![(1, 0), (1, 1)],
+            vec![(1, 2), (1, 1)],
+        ]
+    );
+}
+
+#[test]
+fn test_index_uses_ranks() {
+    let network = NTupleNetwork::new(2, 2, 16, vec![vec![(0, 0), (0, 1)]]).unwrap();
+    let cells = vec![vec![4, 8], vec![0, 65536]];
+    assert_eq!(network.index(&cells, &[(0, 0), (0, 1)]), 2 * 16 + 3);
+    assert_eq!(network.index(&cells, &[(1, 0), (1, 1)]), 15);
+}
+
+#[test]
+fn test_symmetric_boards_have_equal_values() {
+    let mut network = NTupleNetwork::classic_4x4();
+    let cells = vec![
+        vec![2, 4, 8, 16],
+        vec![0, 2, 0, 4],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 2],
+    ];
+    network.update(&cells, 10.0);
+    let mirrored: Vec<Vec<i32>> = cells
+        .iter()
+        .map(|row| row.iter().rev().copied().collect())
+        .collect();
+    let shifted = board::shift_board(&cells, Direction::Left).0;
+    assert!((network.value(&cells) - network.value(&mirrored)).abs() < 1e-4);
+    assert!(network.value(&cells) > 0.0);
+    assert!((network.value(&shifted) - network.value(&cells)).abs() > 1e-4);
+}
+
+#[test]
+fn test_update_moves_value_towards_target() {
+    let mut network = NTupleNetwork::classic_4x4();
+    let cells = vec![
+        vec![2, 0, 0, 0],
+        vec![0, 4, 0, 0],
+        vec![0, 0, 8, 0],
+        vec![0, 0, 0, 16],
+    ];
+    for _ in 0..20 {
+        let error = 100.0 - network.value(&cells);
+        network.update(&cells, 0.5 * error);
+    }
+    assert!((network.value(&cells) - 100.0).abs() < 1.0);
+}
+
+#[test]
+fn test_bytes_round_trip() {
+    let mut network = NTupleNetwork::new(3, 3, 8, vec![vec![(0, 0), (1, 1), (2, 2)]]).unwrap();
+    network.update(&[vec![2, 0, 0], vec![0, 4, 0], vec![0, 0, 8]], 3.0);
+    let bytes = network.to_bytes();
+    assert_eq!(NTupleNetwork::from_bytes(&bytes), Ok(network));
+
+    assert!(NTupleNetwork::from_bytes(b"NOPE").is_err());
+    assert!(NTupleNetwork::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+    let mut trailing = bytes;
+    trailing.extend_from_slice(&[0, 0, 0, 0]);
+    assert!(NTupleNetwork::from_bytes(&trailing).is_err());
+}
+
+#[test]
+fn test_from_bytes_checks_weight_count_before_allocating() {
+    let header = |base: u32, len: u32| {
+        let mut bytes = WEIGHTS_MAGIC.to_vec();
+        for value in &[WEIGHTS_VERSION, 4, 4, base, 1, len] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for _ in 0..len {
+            bytes.extend_from_slice(&[0; 8]);
+        }
+        bytes
+    };
+    // A header describing 16^7 weights with none of them in the file.
+    assert!(NTupleNetwork::from_bytes(&header(16, 7)).is_err());
+    // A header whose weight count overflows.
+    assert!(NTupleNetwork::from_bytes(&header(u32::MAX, 4)).is_err());
+
+    let mut exact = header(2, 2);
+    exact.extend_from_slice(&[0; 16]);
+    assert!(NTupleNetwork::from_bytes(&exact).is_ok());
+    assert!(NTupleNetwork::from_bytes(&exact[..exact.len() - 4]).is_err());
+}
+
+#[test]
+fn test_save_and_load() {
+    let network = NTupleNetwork::classic_4x4();
+    let path = std::env::temp_dir().join(format!("ntuple-test-{}.bin", std::process::id()));
+    network.save(&path).unwrap();
+    let loaded = NTupleNetwork::load(&path);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, Ok(network));
+    assert!(NTupleNetwork::load(&path).is_err());
+}
+
+#[test]
+fn test_trainer_rejects_mismatched_board() {
+    let config = GameConfig {
+        rows: 3,
+        ..GameConfig::default()
+    };
+    assert!(Trainer::new(NTupleNetwork::classic_4x4(), config, 1).is_err());
+}
+
+#[test]
+fn test_trainer_rejects_games_agents_cannot_play() {
+    use crate::board::Movement;
+
+    let walled = GameConfig {
+        walls: vec![(0, 0)],
+        ..GameConfig::default()
+    };
+    let mut mask = vec![vec![true; 4]; 4];
+    mask[3][3] = false;
+    let masked = GameConfig {
+        mask: Some(mask),
+        ..GameConfig::default()
+    };
+    let stepping = GameConfig {
+        movement: Movement::Step,
+        ..GameConfig::default()
+    };
+    let wrapping = GameConfig {
+        movement: Movement::Wrap,
+        ..GameConfig::default()
+    };
+    for config in [walled, masked, stepping, wrapping] {
+        assert!(Trainer::new(NTupleNetwork::classic_4x4(), config, 1).is_err());
+    }
+}
+
+#[test]
+fn test_training_learns_and_feeds_expectimax() {
+    use crate::ai::expectimax::Expectimax;
+
+    let network = NTupleNetwork::new(
+        3,
+        3,
+        12,
+        vec![vec![(0, 0), (0, 1), (0, 2)], vec![(1, 0), (1, 1), (1, 2)]],
+    )
+    .unwrap();
+    let config = GameConfig {
+        rows: 3,
+        cols: 3,
+        ..GameConfig::default()
+    };
+    let mut trainer = Trainer::new(network, config.clone(), 7).unwrap();
+    let scores = trainer.train(200).unwrap();
+    assert_eq!(scores.len(), 200);
+    let early: i32 = scores[..50].iter().sum();
+    let late: i32 = scores[150..].iter().sum();
+    assert!(late > early);
+
+    let empty = vec![vec![0; 3]; 3];
+    assert!(trainer.network.value(&empty) > 0.0);
+    let expectimax = Expectimax::new(config.tile_options, trainer.network);
+    assert!(expectimax
+        .best_move(&[vec![2, 2, 0], vec![0, 0, 0], vec![0, 0, 0]])
+        .is_some());
+}
@@ -0,0 +1,105 @@
+mod simulate;
+mod tui;
+
+use game_lib::game::GameConfig;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "Usage: 2048 [--size N|ROWSxCOLS] [--seed N]\n       2048 simulate --help";
+
+struct PlayOptions {
+    config: GameConfig,
+    seed: u64,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(error) = run(&args) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let help = args.iter().any(|arg| arg == "-h" || arg == "--help");
+    if args.first().map(String::as_str) == Some("simulate") {
+        if help {
+            println!("{}", simulate::USAGE);
+            return Ok(());
+        }
+        return simulate::run(&args[1..]);
+    }
+    if help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let options = parse_play_options(args)?;
+    tui::run(options.config, options.seed)
+}
+
+fn parse_play_options(args: &[String]) -> Result<PlayOptions, String> {
+    let mut options = PlayOptions {
+        config: GameConfig::default(),
+        seed: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--size" => {
+                let (rows, cols) = parse_size(arg, args.next())?;
+                options.config.rows = rows;
+                options.config.cols = cols;
+            }
+            "--seed" => options.seed = parse_value(arg, args.next())?,
+            _ => return Err(format!("Unknown argument: {}\n{}", arg, USAGE)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} expects a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+/// Accepts `N` for a square board or `ROWSxCOLS`.
+fn parse_size(flag: &str, value: Option<&String>) -> Result<(usize, usize), String> {
+    let value = value.ok_or(format!("{} expects a value", flag))?;
+    let invalid = || format!("Invalid value for {}: {}", flag, value);
+    let mut parts = value.splitn(2, 'x');
+    let rows = parts.next().unwrap_or("").parse().map_err(|_| invalid())?;
+    let cols = match parts.next() {
+        Some(cols) => cols.parse().map_err(|_| invalid())?,
+        None => rows,
+    };
+    Ok((rows, cols))
+}
+
+#[cfg(test)]
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn test_parse_play_options() {
+    let options = parse_play_options(&args(&["--size", "5", "--seed", "7"])).unwrap();
+    assert_eq!((options.config.rows, options.config.cols), (5, 5));
+    assert_eq!(options.seed, 7);
+
+    let options = parse_play_options(&args(&["--size", "3x6"])).unwrap();
+    assert_eq!((options.config.rows, options.config.cols), (3, 6));
+    assert!(parse_play_options(&args(&["--size", "3x"])).is_err());
+
+    assert_eq!(
+        parse_play_options(&args(&["--size"])).err().unwrap(),
+        "--size expects a value"
+    );
+    assert_eq!(
+        parse_play_options(&args(&["--seed", "x"])).err().unwrap(),
+        "Invalid value for --seed: x"
+    );
+    assert!(parse_play_options(&args(&["--colour"])).is_err());
+}
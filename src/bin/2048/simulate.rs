@@ -0,0 +1,458 @@
+use crate::{parse_size, parse_value};
+use game_lib::agent::{Agent, CornerAgent, GreedyAgent, RandomAgent};
+use game_lib::ai::expectimax::Expectimax;
+use game_lib::ai::heuristics::Heuristic;
+use game_lib::ai::mcts::{Mcts, Rollout};
+use game_lib::game::GameConfig;
+use game_lib::tile_generator::TileOption;
+use game_lib::tournament::{median, GameResult, Tournament};
+use std::time::Instant;
+
+pub const USAGE: &str = "Usage: 2048 simulate [--agent random|greedy|corner|expectimax|mcts] \
+[--games N] [--size N|ROWSxCOLS] [--tiles VALUE:PERCENT,...] [--seed N] [--threads N] \
+[--format table|json|csv]";
+
+const AGENTS: [&str; 5] = ["random", "greedy", "corner", "expectimax", "mcts"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct SimulateOptions {
+    agent: String,
+    games: usize,
+    config: GameConfig,
+    seed: u64,
+    threads: usize,
+    format: Format,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Distribution {
+    mean: f64,
+    min: f64,
+    p25: f64,
+    median: f64,
+    p75: f64,
+    max: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Summary {
+    agent: String,
+    games: usize,
+    score: Distribution,
+    moves: Distribution,
+    /// Share of games whose largest tile reached at least each tile value.
+    reach: Vec<(i32, f64)>,
+    win_rate: f64,
+    seconds: f64,
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let start = Instant::now();
+    let results = play_games(&options)?;
+    let summary = Summary::new(&options.agent, &results, start.elapsed().as_secs_f64());
+    let output = match options.format {
+        Format::Table => summary.to_table(),
+        Format::Json => summary.to_json(),
+        Format::Csv => summary.to_csv(),
+    };
+    print!("{}", output);
+    Ok(())
+}
+
+fn parse_options(args: &[String]) -> Result<SimulateOptions, String> {
+    let mut options = SimulateOptions {
+        agent: "random".to_string(),
+        games: 100,
+        config: GameConfig::default(),
+        seed: 0,
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        format: Format::Table,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--agent" => options.agent = parse_value(arg, args.next())?,
+            "--games" => options.games = parse_value(arg, args.next())?,
+            "--size" => {
+                let (rows, cols) = parse_size(arg, args.next())?;
+                options.config.rows = rows;
+                options.config.cols = cols;
+            }
+            "--tiles" => {
+                options.config.tile_options =
+                    parse_tiles(&parse_value::<String>(arg, args.next())?)?
+            }
+            "--seed" => options.seed = parse_value(arg, args.next())?,
+            "--threads" => options.threads = parse_value(arg, args.next())?,
+            "--format" => {
+                options.format = match parse_value::<String>(arg, args.next())?.as_str() {
+                    "table" => Format::Table,
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    format => return Err(format!("Unknown format: {}", format)),
+                }
+            }
+            _ => return Err(format!("Unknown argument: {}\n{}", arg, USAGE)),
+        }
+    }
+    if !AGENTS.contains(&options.agent.as_str()) {
+        return Err(format!(
+            "Unknown agent: {}. Available agents: {}",
+            options.agent,
+            AGENTS.join(", ")
+        ));
+    }
+    if options.threads == 0 {
+        return Err("Thread count should be positive".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_tiles(text: &str) -> Result<Vec<TileOption>, String> {
+    text.split(',')
+        .map(|tile| {
+            let mut parts = tile.trim().splitn(2, ':');
+            match (
+                parts.next().and_then(|value| value.parse().ok()),
+                parts
+                    .next()
+                    .and_then(|probability| probability.parse().ok()),
+            ) {
+                (Some(value), Some(probability))
+                    if value > 0 && (0..=100).contains(&probability) =>
+                {
+                    Ok(TileOption { value, probability })
+                }
+                _ => Err(format!(
+                    "Invalid tile option {:?}. Expected VALUE:PERCENT",
+                    tile
+                )),
+            }
+        })
+        .collect()
+}
+
+fn create_agent(
+    name: &str,
+    tile_options: &[TileOption],
+    seed: u64,
+) -> Result<Box<dyn Agent>, String> {
+    Ok(match name {
+        "random" => Box::new(RandomAgent::new(seed)),
+        "greedy" => Box::new(GreedyAgent),
+        "corner" => Box::new(CornerAgent),
+        "expectimax" => Box::new(Expectimax::new(tile_options.to_vec(), Heuristic::default())),
+        "mcts" => Box::new(Mcts::new(
+            tile_options.to_vec(),
+            Rollout::<Heuristic>::Random,
+            seed,
+        )?),
+        _ => return Err(format!("Unknown agent: {}", name)),
+    })
+}
+
+/// Plays game `i` with seed `seed + i`, spreading the games over the worker
+/// threads. Every game gets a fresh agent seeded the same way, so the results
+/// do not depend on the number of threads.
+fn play_games(options: &SimulateOptions) -> Result<Vec<GameResult>, String> {
+    let tournament = Tournament::new(options.config.clone(), options.games, options.seed);
+    let threads = options.threads.min(options.games.max(1));
+    let mut results: Vec<(usize, GameResult)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|thread| {
+                let tournament = &tournament;
+                scope.spawn(move || {
+                    (thread..options.games)
+                        .step_by(threads)
+                        .map(|i| {
+                            let seed = options.seed.wrapping_add(i as u64);
+                            let mut agent =
+                                create_agent(&options.agent, &options.config.tile_options, seed)?;
+                            Ok((i, tournament.play(agent.as_mut(), seed)?))
+                        })
+                        .collect::<Result<Vec<(usize, GameResult)>, String>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .map_err(|_| "Simulation thread panicked".to_string())?
+            })
+            .collect::<Result<Vec<Vec<(usize, GameResult)>>, String>>()
+    })?
+    .into_iter()
+    .flatten()
+    .collect();
+    results.sort_by_key(|(i, _)| *i);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+impl Distribution {
+    fn new(values: &[f64]) -> Distribution {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let percentile = |p: f64| {
+            if sorted.is_empty() {
+                0.0
+            } else {
+                sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+            }
+        };
+        Distribution {
+            mean: if sorted.is_empty() {
+                0.0
+            } else {
+                sorted.iter().sum::<f64>() / sorted.len() as f64
+            },
+            min: percentile(0.0),
+            p25: percentile(0.25),
+            median: median(&sorted),
+            p75: percentile(0.75),
+            max: percentile(1.0),
+        }
+    }
+
+    fn fields(&self) -> [(&'static str, f64); 6] {
+        [
+            ("mean", self.mean),
+            ("min", self.min),
+            ("p25", self.p25),
+            ("median", self.median),
+            ("p75", self.p75),
+            ("max", self.max),
+        ]
+    }
+}
+
+impl Summary {
+    fn new(agent: &str, results: &[GameResult], seconds: f64) -> Summary {
+        let scores: Vec<f64> = results.iter().map(|result| result.score as f64).collect();
+        let moves: Vec<f64> = results.iter().map(|result| result.moves as f64).collect();
+        let smallest = results.iter().map(|result| result.max_tile).min();
+        let largest = results.iter().map(|result| result.max_tile).max();
+        let tiles = std::iter::successors(smallest.filter(|tile| *tile > 0), |tile| {
+            Some(tile * 2).filter(|next| Some(*next) <= largest)
+        });
+        let reach = tiles
+            .map(|tile| {
+                let reached = results
+                    .iter()
+                    .filter(|result| result.max_tile >= tile)
+                    .count();
+                (tile, reached as f64 / results.len() as f64)
+            })
+            .collect();
+        let wins = results.iter().filter(|result| result.won).count();
+        Summary {
+            agent: agent.to_string(),
+            games: results.len(),
+            score: Distribution::new(&scores),
+            moves: Distribution::new(&moves),
+            reach,
+            win_rate: if results.is_empty() {
+                0.0
+            } else {
+                wins as f64 / results.len() as f64
+            },
+            seconds,
+        }
+    }
+
+    fn to_table(&self) -> String {
+        let mut text = String::new();
+        text += &format!("agent     {}\n", self.agent);
+        text += &format!("games     {}\n", self.games);
+        text += &format!("win rate  {:.1}%\n", self.win_rate * 100.0);
+        text += &format!("time      {:.2}s\n\n", self.seconds);
+        text += &format!("{:<8}", "");
+        for (name, _) in self.score.fields().iter() {
+            text += &format!("{:>10}", name);
+        }
+        text += "\n";
+        for (label, distribution) in [("score", &self.score), ("moves", &self.moves)].iter() {
+            text += &format!("{:<8}", label);
+            for (_, value) in distribution.fields().iter() {
+                text += &format!("{:>10.1}", value);
+            }
+            text += "\n";
+        }
+        text += &format!("\n{:<8}{:>10}\n", "tile", "reached");
+        for (tile, rate) in &self.reach {
+            text += &format!("{:<8}{:>9.1}%\n", tile, rate * 100.0);
+        }
+        text
+    }
+
+    fn to_json(&self) -> String {
+        let distribution = |distribution: &Distribution| {
+            let fields: Vec<String> = distribution
+                .fields()
+                .iter()
+                .map(|(name, value)| format!("\"{}\": {}", name, value))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        };
+        let reach: Vec<String> = self
+            .reach
+            .iter()
+            .map(|(tile, rate)| format!("{{\"tile\": {}, \"rate\": {}}}", tile, rate))
+            .collect();
+        format!(
+            "{{\"agent\": \"{}\", \"games\": {}, \"win_rate\": {}, \"seconds\": {}, \
+\"score\": {}, \"moves\": {}, \"reach\": [{}]}}\n",
+            self.agent,
+            self.games,
+            self.win_rate,
+            self.seconds,
+            distribution(&self.score),
+            distribution(&self.moves),
+            reach.join(", ")
+        )
+    }
+
+    fn to_csv(&self) -> String {
+        let mut text = "metric,key,value\n".to_string();
+        text += &format!("games,,{}\n", self.games);
+        text += &format!("win_rate,,{}\n", self.win_rate);
+        text += &format!("seconds,,{}\n", self.seconds);
+        for (label, distribution) in [("score", &self.score), ("moves", &self.moves)].iter() {
+            for (name, value) in distribution.fields().iter() {
+                text += &format!("{},{},{}\n", label, name, value);
+            }
+        }
+        for (tile, rate) in &self.reach {
+            text += &format!("reach,{},{}\n", tile, rate);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+fn result(score: i32, max_tile: i32, moves: usize) -> GameResult {
+    GameResult {
+        seed: 0,
+        score,
+        max_tile,
+        moves,
+        won: false,
+    }
+}
+
+#[test]
+fn test_parse_options() {
+    let args: Vec<String> = [
+        "--agent",
+        "corner",
+        "--games",
+        "10",
+        "--size",
+        "5x3",
+        "--tiles",
+        "2:80,4:20",
+        "--threads",
+        "2",
+        "--format",
+        "csv",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    let options = parse_options(&args).unwrap();
+    assert_eq!(options.agent, "corner");
+    assert_eq!(options.games, 10);
+    assert_eq!((options.config.rows, options.config.cols), (5, 3));
+    assert_eq!(
+        options.config.tile_options,
+        vec![
+            TileOption {
+                value: 2,
+                probability: 80
+            },
+            TileOption {
+                value: 4,
+                probability: 20
+            }
+        ]
+    );
+    assert_eq!(options.threads, 2);
+    assert_eq!(options.format, Format::Csv);
+
+    assert!(parse_options(&["--agent".to_string(), "human".to_string()]).is_err());
+    assert!(parse_options(&["--format".to_string(), "xml".to_string()]).is_err());
+    assert_eq!(
+        parse_tiles("2:90,4").err().unwrap(),
+        "Invalid tile option \"4\". Expected VALUE:PERCENT"
+    );
+    assert!(parse_tiles("2:127,4:-27").is_err());
+    assert!(parse_tiles("2:100,4:101").is_err());
+    assert!(parse_tiles("-1:100").is_err());
+    assert!(parse_tiles("0:100").is_err());
+}
+
+#[test]
+fn test_results_do_not_depend_on_threads() {
+    let mut options = parse_options(&["--games".to_string(), "6".to_string()]).unwrap();
+    options.threads = 1;
+    let single = play_games(&options).unwrap();
+    options.threads = 4;
+    let parallel = play_games(&options).unwrap();
+    assert_eq!(single, parallel);
+    assert_eq!(
+        single
+            .iter()
+            .map(|result| result.seed)
+            .collect::<Vec<u64>>(),
+        vec![0, 1, 2, 3, 4, 5]
+    );
+}
+
+#[test]
+fn test_invalid_tiles_are_reported() {
+    let mut options = parse_options(&[]).unwrap();
+    options.config.tile_options = parse_tiles("2:50,4:10").unwrap();
+    assert!(play_games(&options).is_err());
+    options.config.tile_options = parse_tiles("2:100,4:100").unwrap();
+    assert!(play_games(&options).is_err());
+}
+
+#[test]
+fn test_summary() {
+    let results = [
+        result(100, 64, 50),
+        result(300, 128, 150),
+        result(200, 128, 100),
+        result(500, 512, 200),
+    ];
+    let summary = Summary::new("greedy", &results, 1.0);
+    assert_eq!(summary.score.mean, 275.0);
+    assert_eq!(summary.score.min, 100.0);
+    assert_eq!(summary.score.median, 250.0);
+    assert_eq!(summary.score.max, 500.0);
+    assert_eq!(summary.moves.p25, 100.0);
+    assert_eq!(
+        summary.reach,
+        vec![(64, 1.0), (128, 0.75), (256, 0.25), (512, 0.25)]
+    );
+
+    assert!(summary
+        .to_table()
+        .lines()
+        .any(|line| line.split_whitespace().collect::<Vec<&str>>() == ["128", "75.0%"]));
+    assert!(summary
+        .to_json()
+        .contains("\"reach\": [{\"tile\": 64, \"rate\": 1}, {\"tile\": 128, \"rate\": 0.75}"));
+    assert!(summary
+        .to_csv()
+        .contains("score,median,250\nscore,p75,300\n"));
+}
@@ -0,0 +1,354 @@
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use game_lib::board::{Direction, WALL};
+use game_lib::game::{Game, GameConfig, GameState};
+use game_lib::random::Pcg32;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+const MIN_CELL_WIDTH: u16 = 6;
+const MAX_CELL_WIDTH: u16 = 12;
+const HELP: &str = "arrows/WASD: move  u: undo  n: new game  q: quit";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Move(Direction),
+    Undo,
+    NewGame,
+    Quit,
+}
+
+struct App {
+    config: GameConfig,
+    seed: u64,
+    game: Game<Pcg32>,
+    best: i32,
+}
+
+impl App {
+    fn new(config: GameConfig, seed: u64, best: i32) -> Result<App, String> {
+        let game = Game::new(config.clone(), Pcg32::new(seed))?;
+        Ok(App {
+            config,
+            seed,
+            game,
+            best,
+        })
+    }
+
+    /// Returns false once the player has asked to quit.
+    fn apply(&mut self, action: Action) -> Result<bool, String> {
+        match action {
+            Action::Move(direction) => {
+                self.game.make_move(direction);
+                self.best = self.best.max(self.game.score());
+            }
+            Action::Undo => {
+                let _ = self.game.undo();
+            }
+            Action::NewGame => {
+                self.seed = self.seed.wrapping_add(1);
+                self.game = Game::new(self.config.clone(), Pcg32::new(self.seed))?;
+            }
+            Action::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+pub fn run(config: GameConfig, seed: u64) -> Result<(), String> {
+    let mut app = App::new(config, seed, load_best())?;
+    let mut out = io::stdout();
+    terminal::enable_raw_mode().map_err(|error| error.to_string())?;
+    let result = execute!(out, EnterAlternateScreen, Hide)
+        .map_err(|error| error.to_string())
+        .and_then(|_| event_loop(&mut out, &mut app));
+    let _ = execute!(out, ResetColor, Show, LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+    save_best(app.best);
+    result
+}
+
+fn event_loop(out: &mut impl Write, app: &mut App) -> Result<(), String> {
+    loop {
+        terminal::size()
+            .and_then(|size| draw(out, app, size))
+            .map_err(|error| error.to_string())?;
+        if let Event::Key(key) = event::read().map_err(|error| error.to_string())? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let Some(action) = action(key) {
+                if !app.apply(action)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn action(key: KeyEvent) -> Option<Action> {
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        return match key.code {
+            KeyCode::Char('c') => Some(Action::Quit),
+            _ => None,
+        };
+    }
+    match key.code {
+        KeyCode::Left | KeyCode::Char('a') | KeyCode::Char('A') => {
+            Some(Action::Move(Direction::Left))
+        }
+        KeyCode::Right | KeyCode::Char('d') | KeyCode::Char('D') => {
+            Some(Action::Move(Direction::Right))
+        }
+        KeyCode::Up | KeyCode::Char('w') | KeyCode::Char('W') => Some(Action::Move(Direction::Up)),
+        KeyCode::Down | KeyCode::Char('s') | KeyCode::Char('S') => {
+            Some(Action::Move(Direction::Down))
+        }
+        KeyCode::Char('u') | KeyCode::Char('U') | KeyCode::Backspace => Some(Action::Undo),
+        KeyCode::Char('n') | KeyCode::Char('N') => Some(Action::NewGame),
+        KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+/// Width and height of a tile so that the whole board, with a one character
+/// gap between tiles, fits in a terminal of the given size.
+fn cell_size(rows: usize, cols: usize, (width, height): (u16, u16)) -> (u16, u16) {
+    let cell_width = (width.saturating_sub(2) / cols as u16)
+        .saturating_sub(1)
+        .clamp(MIN_CELL_WIDTH, MAX_CELL_WIDTH);
+    let cell_height = (height.saturating_sub(5) / rows as u16)
+        .saturating_sub(1)
+        .clamp(1, cell_width / 2);
+    (cell_width, cell_height)
+}
+
+fn tile_colors(value: i32) -> (Color, Color) {
+    let dark = Color::Rgb {
+        r: 119,
+        g: 110,
+        b: 101,
+    };
+    let light = Color::Rgb {
+        r: 249,
+        g: 246,
+        b: 242,
+    };
+    let (r, g, b) = match value {
+        WALL => (119, 110, 101),
+        0 => (205, 193, 180),
+        2 => (238, 228, 218),
+        4 => (237, 224, 200),
+        8 => (242, 177, 121),
+        16 => (245, 149, 99),
+        32 => (246, 124, 95),
+        64 => (246, 94, 59),
+        128 => (237, 207, 114),
+        256 => (237, 204, 97),
+        512 => (237, 200, 80),
+        1024 => (237, 197, 63),
+        2048 => (237, 194, 46),
+        _ => (60, 58, 50),
+    };
+    let foreground = if value <= 4 { dark } else { light };
+    (Color::Rgb { r, g, b }, foreground)
+}
+
+fn draw(out: &mut impl Write, app: &App, size: (u16, u16)) -> io::Result<()> {
+    let cells = app.game.cells();
+    let (rows, cols) = (cells.rows(), cells.cols());
+    let (cell_width, cell_height) = cell_size(rows, cols, size);
+    let mut header = format!(
+        "2048   Score: {}   Best: {}   Moves: {}",
+        app.game.score(),
+        app.best,
+        app.game.moves()
+    );
+    if let Some(next) = app.game.next_tile() {
+        header += &format!("   Next: {}", next);
+    }
+    queue!(
+        out,
+        ResetColor,
+        Clear(ClearType::All),
+        MoveTo(1, 0),
+        Print(header)
+    )?;
+
+    let top = 2;
+    for (i, (row, active)) in cells.row_iter().zip(app.game.mask().row_iter()).enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            if !active[j] {
+                continue;
+            }
+            let x = 1 + j as u16 * (cell_width + 1);
+            let y = top + i as u16 * (cell_height + 1);
+            let (background, foreground) = tile_colors(*value);
+            queue!(
+                out,
+                SetBackgroundColor(background),
+                SetForegroundColor(foreground)
+            )?;
+            for line in 0..cell_height {
+                let text = if line == cell_height / 2 && *value > 0 {
+                    value.to_string()
+                } else {
+                    String::new()
+                };
+                queue!(
+                    out,
+                    MoveTo(x, y + line),
+                    Print(format!("{:^width$}", text, width = cell_width as usize))
+                )?;
+            }
+        }
+    }
+    let board_width = cols as u16 * (cell_width + 1);
+    let board_height = rows as u16 * (cell_height + 1);
+    queue!(out, ResetColor, MoveTo(1, top + board_height), Print(HELP))?;
+
+    let message = match app.game.state() {
+        GameState::InProgress => None,
+        GameState::Won => Some("You win!"),
+        GameState::Lost => Some("Game over"),
+    };
+    if let Some(message) = message {
+        let lines = [message, "", "n: new game  u: undo  q: quit"];
+        let width = lines.iter().map(|line| line.len()).max().unwrap_or(0) as u16 + 4;
+        let x = 1 + board_width.saturating_sub(width) / 2;
+        let y = top + board_height.saturating_sub(lines.len() as u16 + 2) / 2;
+        queue!(
+            out,
+            SetBackgroundColor(Color::Black),
+            SetForegroundColor(Color::White)
+        )?;
+        for (k, line) in [""]
+            .iter()
+            .chain(lines.iter())
+            .chain([""].iter())
+            .enumerate()
+        {
+            queue!(
+                out,
+                MoveTo(x, y + k as u16),
+                Print(format!("{:^width$}", line, width = width as usize))
+            )?;
+        }
+        queue!(out, ResetColor)?;
+    }
+    out.flush()
+}
+
+fn best_score_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".2048_best"))
+}
+
+fn load_best() -> i32 {
+    best_score_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| text.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn save_best(best: i32) {
+    if best > load_best() {
+        if let Some(path) = best_score_path() {
+            let _ = std::fs::write(path, best.to_string());
+        }
+    }
+}
+
+#[test]
+fn test_action() {
+    let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+    assert_eq!(
+        action(key(KeyCode::Left)),
+        Some(Action::Move(Direction::Left))
+    );
+    assert_eq!(
+        action(key(KeyCode::Char('w'))),
+        Some(Action::Move(Direction::Up))
+    );
+    assert_eq!(
+        action(key(KeyCode::Char('S'))),
+        Some(Action::Move(Direction::Down))
+    );
+    assert_eq!(action(key(KeyCode::Char('u'))), Some(Action::Undo));
+    assert_eq!(action(key(KeyCode::Char('n'))), Some(Action::NewGame));
+    assert_eq!(action(key(KeyCode::Esc)), Some(Action::Quit));
+    assert_eq!(action(key(KeyCode::Char('x'))), None);
+    assert_eq!(
+        action(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+        Some(Action::Quit)
+    );
+}
+
+#[test]
+fn test_cell_size() {
+    assert_eq!(cell_size(4, 4, (80, 24)), (12, 3));
+    assert_eq!(cell_size(4, 4, (30, 12)), (6, 1));
+    assert_eq!(cell_size(8, 8, (200, 80)), (12, 6));
+}
+
+#[test]
+fn test_app() {
+    let mut app = App::new(GameConfig::default(), 1, 0).unwrap();
+    let direction = app.game.legal_moves()[0];
+    assert!(app.apply(Action::Move(direction)).unwrap());
+    assert_eq!(app.game.moves(), 1);
+    assert!(app.best >= app.game.score());
+
+    assert!(app.apply(Action::Undo).unwrap());
+    assert_eq!(app.game.moves(), 0);
+
+    assert!(app.apply(Action::NewGame).unwrap());
+    assert_eq!(app.seed, 2);
+    assert_eq!(app.game.moves(), 0);
+
+    assert!(!app.apply(Action::Quit).unwrap());
+}
+
+#[test]
+fn test_draw() {
+    let app = App::new(GameConfig::default(), 1, 100).unwrap();
+    let mut out = vec![];
+    draw(&mut out, &app, (80, 24)).unwrap();
+    let text = String::from_utf8_lossy(&out);
+    assert!(text.contains("Best: 100"));
+    assert!(text.contains(HELP));
+    assert!(!text.contains("Game over"));
+    assert!(!text.contains("Next:"));
+
+    let config = GameConfig {
+        tile_bag: Some(vec![4]),
+        ..GameConfig::default()
+    };
+    let app = App::new(config, 1, 0).unwrap();
+    let mut out = vec![];
+    draw(&mut out, &app, (80, 24)).unwrap();
+    assert!(String::from_utf8_lossy(&out).contains("Next: 4"));
+
+    let config = GameConfig {
+        walls: vec![(0, 0)],
+        ..GameConfig::default()
+    };
+    let app = App::new(config, 1, 0).unwrap();
+    let mut out = vec![];
+    draw(&mut out, &app, (80, 24)).unwrap();
+    assert!(!String::from_utf8_lossy(&out).contains("-1"));
+
+    let config = GameConfig {
+        mask: Some(game_lib::shape::ring(4)),
+        ..GameConfig::default()
+    };
+    let ring = App::new(config, 1, 0).unwrap();
+    let full = App::new(GameConfig::default(), 1, 0).unwrap();
+    let (mut ring_out, mut full_out) = (vec![], vec![]);
+    draw(&mut ring_out, &ring, (80, 24)).unwrap();
+    draw(&mut full_out, &full, (80, 24)).unwrap();
+    assert!(ring_out.len() < full_out.len());
+}
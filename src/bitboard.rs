@@ -0,0 +1,349 @@
+use crate::board::{Direction, DIRECTIONS};
+use std::sync::OnceLock;
+
+const MAX_EXPONENT: u8 = 15;
+const ROW_MASK: u64 = 0xffff;
+
+/// A 4x4 board packed into 16 four-bit exponents. Cell `(i, j)` lives in
+/// nibble `4 * i + j` counting from the least significant end, and an
+/// exponent `e` stands for the tile `2^e` (0 for an empty cell). Tiles
+/// therefore go up to 32768, and two 32768 tiles never merge. `from_cells`
+/// only accepts tiles up to 16384, so the boards it builds shift like
+/// `board::shift_board` until a move makes a 32768.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Bitboard(pub u64);
+
+struct RowTables {
+    left: Vec<u16>,
+    right: Vec<u16>,
+    left_score: Vec<i32>,
+    right_score: Vec<i32>,
+}
+
+impl Bitboard {
+    pub fn from_cells(cells: &[Vec<i32>]) -> Result<Bitboard, String> {
+        if cells.len() != 4 || cells.iter().any(|row| row.len() != 4) {
+            return Err("Bitboards only support 4x4 boards".to_string());
+        }
+        let mut board = Bitboard(0);
+        for (i, row) in cells.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                let exponent = tile_to_exponent(*cell)?;
+                if exponent == MAX_EXPONENT {
+                    return Err(format!(
+                        "Bitboards cannot merge {} tiles, so boards should not hold them",
+                        cell
+                    ));
+                }
+                board.set(i, j, exponent);
+            }
+        }
+        Ok(board)
+    }
+
+    pub fn to_cells(self) -> Vec<Vec<i32>> {
+        (0..4)
+            .map(|i| (0..4).map(|j| exponent_to_tile(self.get(i, j))).collect())
+            .collect()
+    }
+
+    pub fn get(self, row: usize, col: usize) -> u8 {
+        ((self.0 >> (16 * row + 4 * col)) & 0xf) as u8
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, exponent: u8) {
+        let shift = 16 * row + 4 * col;
+        self.0 = (self.0 & !(0xf << shift)) | (((exponent & 0xf) as u64) << shift);
+    }
+
+    pub fn row(self, row: usize) -> u16 {
+        ((self.0 >> (16 * row)) & ROW_MASK) as u16
+    }
+
+    pub fn empty_cells(self) -> Vec<(usize, usize)> {
+        let mut empty_cells = vec![];
+        for i in 0..4 {
+            for j in 0..4 {
+                if self.get(i, j) == 0 {
+                    empty_cells.push((i, j));
+                }
+            }
+        }
+        empty_cells
+    }
+
+    pub fn max_exponent(self) -> u8 {
+        (0..16)
+            .map(|index| ((self.0 >> (4 * index)) & 0xf) as u8)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn transpose(self) -> Bitboard {
+        let x = self.0;
+        let a1 = x & 0xf0f0_0f0f_f0f0_0f0f;
+        let a2 = x & 0x0000_f0f0_0000_f0f0;
+        let a3 = x & 0x0f0f_0000_0f0f_0000;
+        let a = a1 | (a2 << 12) | (a3 >> 12);
+        let b1 = a & 0xff00_ff00_00ff_00ff;
+        let b2 = a & 0x00ff_00ff_0000_0000;
+        let b3 = a & 0x0000_0000_ff00_ff00;
+        Bitboard(b1 | (b2 >> 24) | (b3 << 24))
+    }
+
+    pub fn shift(self, direction: Direction) -> (Bitboard, i32) {
+        let tables = row_tables();
+        match direction {
+            Direction::Left => self.shift_rows(&tables.left, &tables.left_score),
+            Direction::Right => self.shift_rows(&tables.right, &tables.right_score),
+            Direction::Up => {
+                let (board, score) = self.transpose().shift(Direction::Left);
+                (board.transpose(), score)
+            }
+            Direction::Down => {
+                let (board, score) = self.transpose().shift(Direction::Right);
+                (board.transpose(), score)
+            }
+        }
+    }
+
+    pub fn legal_moves(self) -> Vec<Direction> {
+        DIRECTIONS
+            .iter()
+            .copied()
+            .filter(|direction| self.shift(*direction).0 != self)
+            .collect()
+    }
+
+    fn shift_rows(self, rows: &[u16], scores: &[i32]) -> (Bitboard, i32) {
+        let mut board = 0;
+        let mut score = 0;
+        for i in 0..4 {
+            let row = self.row(i) as usize;
+            board |= (rows[row] as u64) << (16 * i);
+            score += scores[row];
+        }
+        (Bitboard(board), score)
+    }
+}
+
+pub fn tile_to_exponent(tile: i32) -> Result<u8, String> {
+    if tile == 0 {
+        return Ok(0);
+    }
+    if tile < 2 || tile.count_ones() != 1 || tile.trailing_zeros() > MAX_EXPONENT as u32 {
+        return Err(format!(
+            "Bitboards only support powers of two from 2 to 32768. Actual tile: {}",
+            tile
+        ));
+    }
+    Ok(tile.trailing_zeros() as u8)
+}
+
+pub fn exponent_to_tile(exponent: u8) -> i32 {
+    if exponent == 0 {
+        0
+    } else {
+        1 << exponent
+    }
+}
+
+fn row_tables() -> &'static RowTables {
+    static TABLES: OnceLock<RowTables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = RowTables {
+            left: vec![0; 1 << 16],
+            right: vec![0; 1 << 16],
+            left_score: vec![0; 1 << 16],
+            right_score: vec![0; 1 << 16],
+        };
+        for row in 0..=u16::MAX {
+            let (shifted, score) = shift_row_left(row);
+            tables.left[row as usize] = shifted;
+            tables.left_score[row as usize] = score;
+            let reversed = reverse_row(row) as usize;
+            tables.right[reversed] = reverse_row(shifted);
+            tables.right_score[reversed] = score;
+        }
+        tables
+    })
+}
+
+fn shift_row_left(row: u16) -> (u16, i32) {
+    let mut cells: Vec<u8> = (0..4)
+        .map(|j| ((row >> (4 * j)) & 0xf) as u8)
+        .filter(|exponent| *exponent != 0)
+        .collect();
+    let mut score = 0;
+    let mut i = 0;
+    while i + 1 < cells.len() {
+        if cells[i] == cells[i + 1] && cells[i] < MAX_EXPONENT {
+            cells[i] += 1;
+            cells.remove(i + 1);
+            score += exponent_to_tile(cells[i]);
+        }
+        i += 1;
+    }
+    let shifted = cells.iter().enumerate().fold(0, |acc, (j, exponent)| {
+        acc | ((*exponent as u16) << (4 * j))
+    });
+    (shifted, score)
+}
+
+fn reverse_row(row: u16) -> u16 {
+    (row >> 12) | ((row >> 4) & 0x00f0) | ((row << 4) & 0x0f00) | (row << 12)
+}
+
+#[cfg(test)]
+fn random_cells(rng: &mut crate::random::Pcg32) -> Vec<Vec<i32>> {
+    use crate::random::RandomNumberGenerator;
+
+    (0..4)
+        .map(|_| {
+            (0..4)
+                .map(|_| exponent_to_tile(rng.next_in_range(0..12) as u8))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_from_cells_round_trip() {
+    let cells = vec![
+        vec![0, 2, 4, 8],
+        vec![16, 32, 64, 128],
+        vec![256, 512, 1024, 2048],
+        vec![4096, 8192, 16384, 0],
+    ];
+    let board = Bitboard::from_cells(&cells).unwrap();
+    assert_eq!(board.0, 0x0edc_ba98_7654_3210);
+    assert_eq!(board.to_cells(), cells);
+    assert_eq!(Bitboard(0xfedc_ba98_7654_3210).to_cells()[3][3], 32768);
+}
+
+#[test]
+fn test_from_cells_rejects_unsupported_boards() {
+    assert!(Bitboard::from_cells(&[vec![0, 2], vec![2, 0]]).is_err());
+    assert!(Bitboard::from_cells(&[
+        vec![0, 0, 0, 0],
+        vec![0, 3, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+    ])
+    .is_err());
+    assert!(Bitboard::from_cells(&[
+        vec![0, 0, 0, 0],
+        vec![0, 65536, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_transpose() {
+    let cells = vec![
+        vec![2, 4, 8, 16],
+        vec![32, 64, 128, 256],
+        vec![512, 1024, 2048, 4096],
+        vec![8192, 16384, 2, 0],
+    ];
+    let transposed = Bitboard::from_cells(&cells).unwrap().transpose();
+    assert_eq!(
+        transposed.to_cells(),
+        vec![
+            vec![2, 32, 512, 8192],
+            vec![4, 64, 1024, 16384],
+            vec![8, 128, 2048, 2],
+            vec![16, 256, 4096, 0],
+        ]
+    );
+    assert_eq!(transposed.transpose().to_cells(), cells);
+}
+
+#[test]
+fn test_reverse_row() {
+    assert_eq!(reverse_row(0x4321), 0x1234);
+    assert_eq!(reverse_row(0xf00a), 0xa00f);
+}
+
+#[test]
+fn test_shift() {
+    let cells = vec![
+        vec![2, 0, 2, 0],
+        vec![0, 4, 4, 2],
+        vec![2, 2, 2, 2],
+        vec![2, 4, 2, 4],
+    ];
+    let board = Bitboard::from_cells(&cells).unwrap();
+    let (shifted, score) = board.shift(Direction::Left);
+    assert_eq!(
+        shifted.to_cells(),
+        vec![
+            vec![4, 0, 0, 0],
+            vec![8, 2, 0, 0],
+            vec![4, 4, 0, 0],
+            vec![2, 4, 2, 4],
+        ]
+    );
+    assert_eq!(score, 20);
+}
+
+#[test]
+fn test_max_tiles_do_not_merge() {
+    let cells = vec![
+        vec![32768, 32768, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+    ];
+    assert_eq!(
+        Bitboard::from_cells(&cells),
+        Err("Bitboards cannot merge 32768 tiles, so boards should not hold them".to_string())
+    );
+
+    let cells = vec![
+        vec![16384, 16384, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+        vec![0, 0, 0, 0],
+    ];
+    let (shifted, score) = Bitboard::from_cells(&cells).unwrap().shift(Direction::Left);
+    assert_eq!(shifted.to_cells()[0], vec![32768, 0, 0, 0]);
+    assert_eq!(score, 32768);
+    let mut pair = shifted;
+    pair.set(0, 1, MAX_EXPONENT);
+    assert_eq!(pair.shift(Direction::Left), (pair, 0));
+}
+
+#[test]
+fn test_matches_shift_board() {
+    let mut rng = crate::random::Pcg32::new(9);
+    for _ in 0..2000 {
+        let cells = random_cells(&mut rng);
+        let board = Bitboard::from_cells(&cells).unwrap();
+        for direction in DIRECTIONS.iter() {
+            let (expected_cells, expected_score) = crate::board::shift_board(&cells, *direction);
+            let (shifted, score) = board.shift(*direction);
+            assert_eq!(shifted.to_cells(), expected_cells);
+            assert_eq!(score, expected_score);
+        }
+        assert_eq!(board.legal_moves(), crate::board::legal_moves(&cells));
+    }
+}
+
+#[test]
+fn test_empty_cells_and_max_exponent() {
+    let cells = vec![
+        vec![4, 0, 0, 0],
+        vec![8, 2, 0, 0],
+        vec![4, 4, 0, 0],
+        vec![2, 4, 2, 4],
+    ];
+    let board = Bitboard::from_cells(&cells).unwrap();
+    assert_eq!(
+        board.empty_cells(),
+        crate::board::select_empty_cells(&cells)
+    );
+    assert_eq!(board.max_exponent(), 3);
+}
@@ -0,0 +1,1527 @@
+use crate::board::{Direction, Movement, WALL};
+use crate::matrix::Matrix;
+use crate::merge_rule::{ClassicMerge, MergeRule};
+use crate::new_board::Board;
+use crate::random::{RandomNumberGenerator, RngState, SnapshotGenerator};
+use crate::tile_generator::{TileGenerator, TileOption};
+
+pub const MIN_SIZE: usize = 2;
+pub const MAX_SIZE: usize = 16;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameConfig {
+    pub rows: usize,
+    pub cols: usize,
+    pub tile_options: Vec<TileOption>,
+    pub initial_tiles: usize,
+    pub win_target: i32,
+    pub endless: bool,
+    pub undo_limit: Option<usize>,
+    pub undo_penalty: i32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub movement: Movement,
+    /// When set, tiles are drawn from a shuffled bag of these values instead
+    /// of `tile_options`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tile_bag: Option<Vec<i32>>,
+    /// Blocked cells as (row, column). Tiles cannot enter, pass or merge
+    /// through them, and they split lines into separately shifted segments.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub walls: Vec<(usize, usize)>,
+    /// The shape of the board: `mask[i][j]` is false for cells outside it.
+    /// Tiles move along lines through the active cells only.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mask: Option<Vec<Vec<bool>>>,
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig {
+            rows: 4,
+            cols: 4,
+            tile_options: vec![
+                TileOption {
+                    value: 2,
+                    probability: 90,
+                },
+                TileOption {
+                    value: 4,
+                    probability: 10,
+                },
+            ],
+            initial_tiles: 2,
+            win_target: 2048,
+            endless: false,
+            undo_limit: None,
+            undo_penalty: 0,
+            movement: Movement::Slide,
+            tile_bag: None,
+            walls: vec![],
+            mask: None,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Defaults for games played with `FibonacciMerge`.
+    pub fn fibonacci() -> GameConfig {
+        GameConfig {
+            tile_options: vec![
+                TileOption {
+                    value: 1,
+                    probability: 90,
+                },
+                TileOption {
+                    value: 2,
+                    probability: 10,
+                },
+            ],
+            win_target: 2584,
+            ..GameConfig::default()
+        }
+    }
+
+    /// Defaults for games played with `BaseMerge`: tiles of `base` and
+    /// `base` squared, and the first power of `base` from 2048 as the target.
+    /// Fails for bases below 2 or whose square does not fit in a tile.
+    pub fn powers_of(base: i32) -> Result<GameConfig, String> {
+        if base < 2 {
+            return Err(format!("Base should be at least 2. Actual base: {}", base));
+        }
+        let too_large = || format!("Base {} is too large for its tiles to fit", base);
+        let square = base.checked_mul(base).ok_or_else(too_large)?;
+        let mut win_target = base;
+        while win_target < 2048 {
+            win_target = win_target.checked_mul(base).ok_or_else(too_large)?;
+        }
+        Ok(GameConfig {
+            tile_options: vec![
+                TileOption {
+                    value: base,
+                    probability: 90,
+                },
+                TileOption {
+                    value: square,
+                    probability: 10,
+                },
+            ],
+            win_target,
+            ..GameConfig::default()
+        })
+    }
+
+    /// Defaults for games played with `ThreesMerge`: tiles step one cell,
+    /// nine tiles to start, and new tiles drawn from a bag of twelve.
+    pub fn threes() -> GameConfig {
+        GameConfig {
+            tile_options: vec![
+                TileOption {
+                    value: 1,
+                    probability: 33,
+                },
+                TileOption {
+                    value: 2,
+                    probability: 33,
+                },
+                TileOption {
+                    value: 3,
+                    probability: 34,
+                },
+            ],
+            initial_tiles: 9,
+            win_target: 768,
+            movement: Movement::Step,
+            tile_bag: Some(vec![1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3]),
+            ..GameConfig::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveOutcome {
+    Moved {
+        score: i32,
+        spawned: Option<(usize, usize, i32)>,
+    },
+    Illegal,
+    GameOver,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameState {
+    InProgress,
+    Won,
+    Lost,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Milestone {
+    pub tile: i32,
+    pub moves: usize,
+}
+
+#[derive(Clone)]
+struct Snapshot<R: RandomNumberGenerator, M: MergeRule> {
+    board: Board<R, M>,
+    score: i32,
+    milestones: Vec<Milestone>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedGame {
+    pub config: GameConfig,
+    pub cells: Vec<Vec<i32>>,
+    pub score: i32,
+    pub moves: usize,
+    pub undos: usize,
+    pub milestones: Vec<Milestone>,
+    pub rng_state: RngState,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bag: Option<Vec<i32>>,
+}
+
+pub struct Game<R: RandomNumberGenerator + Clone, M: MergeRule + Clone = ClassicMerge> {
+    config: GameConfig,
+    board: Board<R, M>,
+    score: i32,
+    moves: usize,
+    milestones: Vec<Milestone>,
+    history: Vec<Snapshot<R, M>>,
+    history_start: usize,
+    directions: Vec<Direction>,
+    undos: usize,
+    penalty: i32,
+}
+
+impl<R: RandomNumberGenerator + Clone> Game<R> {
+    pub fn new(config: GameConfig, rng: R) -> Result<Game<R>, String> {
+        Game::with_rule(config, rng, ClassicMerge)
+    }
+}
+
+impl<R: RandomNumberGenerator + Clone, M: MergeRule + Clone> Game<R, M> {
+    pub fn with_rule(config: GameConfig, rng: R, rule: M) -> Result<Game<R, M>, String> {
+        validate_config(&config)?;
+        let generator = create_generator(&config, rng)?;
+        let mut cells = vec![vec![0; config.cols]; config.rows];
+        for (i, j) in &config.walls {
+            cells[*i][*j] = WALL;
+        }
+        let mut board = Board::from_cells(cells, board_mask(&config), generator, rule);
+        for _ in 0..config.initial_tiles {
+            board.add_random_tile();
+        }
+        let mut game = Game::from_board(config, board, 0, 0);
+        game.record_milestones();
+        game.history = vec![game.snapshot()];
+        Ok(game)
+    }
+
+    fn from_board(config: GameConfig, board: Board<R, M>, score: i32, moves: usize) -> Game<R, M> {
+        Game {
+            config,
+            board,
+            score,
+            moves,
+            milestones: vec![],
+            history: vec![],
+            history_start: moves,
+            directions: vec![],
+            undos: 0,
+            penalty: 0,
+        }
+    }
+
+    pub fn make_move(&mut self, direction: Direction) -> MoveOutcome {
+        if self.is_over() {
+            return MoveOutcome::GameOver;
+        }
+        if !self
+            .board
+            .legal_moves(self.config.movement)
+            .contains(&direction)
+        {
+            return MoveOutcome::Illegal;
+        }
+        let (score, edges) = self.board.shift(direction, self.config.movement);
+        self.score += score;
+        self.moves += 1;
+        let spawned = match self.config.movement {
+            Movement::Slide | Movement::Wrap => self.board.add_random_tile(),
+            Movement::Step => self.board.add_edge_tile(&edges),
+        };
+        self.record_milestones();
+        self.history.truncate(self.moves - self.history_start);
+        self.history.push(self.snapshot());
+        self.directions
+            .truncate(self.moves - self.history_start - 1);
+        self.directions.push(direction);
+        MoveOutcome::Moved { score, spawned }
+    }
+
+    pub fn undo(&mut self) -> Result<(), String> {
+        if self.moves == self.history_start {
+            return Err("There is no move to undo".to_string());
+        }
+        self.jump_to(self.moves - 1)
+    }
+
+    pub fn redo(&mut self) -> Result<(), String> {
+        self.jump_to(self.moves + 1)
+    }
+
+    pub fn jump_to(&mut self, moves: usize) -> Result<(), String> {
+        let last_move = self.history_start + self.history.len() - 1;
+        if moves < self.history_start || moves > last_move {
+            return Err(format!(
+                "Cannot jump to move {}. Recorded moves: {} to {}",
+                moves, self.history_start, last_move
+            ));
+        }
+        let undos = self.moves.saturating_sub(moves);
+        if let Some(limit) = self.config.undo_limit {
+            if self.undos + undos > limit {
+                return Err(format!(
+                    "Undo limit of {} reached. Undos left: {}",
+                    limit,
+                    limit.saturating_sub(self.undos)
+                ));
+            }
+        }
+        self.undos += undos;
+        let undos = undos.min(i32::MAX as usize) as i32;
+        self.penalty = self
+            .penalty
+            .saturating_add(undos.saturating_mul(self.config.undo_penalty));
+        let snapshot = self.history[moves - self.history_start].clone();
+        self.board = snapshot.board;
+        self.score = snapshot.score;
+        self.milestones = snapshot.milestones;
+        self.moves = moves;
+        Ok(())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.moves > self.history_start
+            && self
+                .config
+                .undo_limit
+                .is_none_or(|limit| self.undos < limit)
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.moves + 1 < self.history_start + self.history.len()
+    }
+
+    pub fn move_history(&self) -> &[Direction] {
+        &self.directions[..self.moves - self.history_start]
+    }
+
+    pub fn undos(&self) -> usize {
+        self.undos
+    }
+
+    /// The merge score minus undo penalties, which never take it below zero.
+    pub fn score(&self) -> i32 {
+        self.score.saturating_sub(self.penalty).max(0)
+    }
+
+    pub fn moves(&self) -> usize {
+        self.moves
+    }
+
+    pub fn cells(&self) -> &Matrix<i32> {
+        self.board.cells()
+    }
+
+    /// Which cells belong to the board. Renderers should leave the others
+    /// blank.
+    pub fn mask(&self) -> &Matrix<bool> {
+        self.board.mask()
+    }
+
+    pub fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    pub fn milestones(&self) -> &[Milestone] {
+        &self.milestones
+    }
+
+    /// The tile the next move will spawn, when tiles come from a bag.
+    pub fn next_tile(&self) -> Option<i32> {
+        self.board.next_tile()
+    }
+
+    pub fn max_tile(&self) -> i32 {
+        max_tile(self.board.cells())
+    }
+
+    pub fn legal_moves(&self) -> Vec<Direction> {
+        if !self.config.endless && !self.milestones.is_empty() {
+            return vec![];
+        }
+        self.board.legal_moves(self.config.movement)
+    }
+
+    pub fn state(&self) -> GameState {
+        if !self.config.endless && !self.milestones.is_empty() {
+            GameState::Won
+        } else if self.board.legal_moves(self.config.movement).is_empty() {
+            GameState::Lost
+        } else {
+            GameState::InProgress
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.state() != GameState::InProgress
+    }
+
+    pub fn rule(&self) -> &M {
+        self.board.rule()
+    }
+
+    fn snapshot(&self) -> Snapshot<R, M> {
+        Snapshot {
+            board: self.board.clone(),
+            score: self.score,
+            milestones: self.milestones.clone(),
+        }
+    }
+
+    fn record_milestones(&mut self) {
+        let max_tile = self.max_tile();
+        let mut next = self
+            .milestones
+            .last()
+            .map_or(self.config.win_target, |milestone| {
+                self.board.rule().next_milestone(milestone.tile)
+            });
+        while max_tile >= next {
+            self.milestones.push(Milestone {
+                tile: next,
+                moves: self.moves,
+            });
+            next = self.board.rule().next_milestone(next);
+        }
+    }
+}
+
+impl<R: SnapshotGenerator + Clone, M: MergeRule + Clone> Game<R, M> {
+    pub fn rng_state(&self) -> RngState {
+        self.board.rng_state()
+    }
+
+    pub fn restore_rng_state(&mut self, state: &RngState) -> Result<(), String> {
+        self.board.restore_rng_state(state)
+    }
+
+    pub fn to_saved(&self) -> SavedGame {
+        SavedGame {
+            config: self.config.clone(),
+            cells: self.cells().to_vec(),
+            score: self.score(),
+            moves: self.moves,
+            undos: self.undos,
+            milestones: self.milestones.clone(),
+            rng_state: self.rng_state(),
+            bag: self.board.bag().map(|bag| bag.to_vec()),
+        }
+    }
+
+    pub fn from_saved_with_rule(saved: SavedGame, rng: R, rule: M) -> Result<Game<R, M>, String> {
+        validate_config(&saved.config)?;
+        validate_cells(&saved.config, &saved.cells, &rule)?;
+        if saved.score < 0 {
+            return Err(format!(
+                "Score should not be negative. Actual score: {}",
+                saved.score
+            ));
+        }
+        if let Some(limit) = saved.config.undo_limit {
+            if saved.undos > limit {
+                return Err(format!(
+                    "Saved game used {} undos, but the limit is {}",
+                    saved.undos, limit
+                ));
+            }
+        }
+        let mut generator = create_generator(&saved.config, rng)?;
+        generator.restore_rng_state(&saved.rng_state)?;
+        match saved.bag {
+            Some(bag) => generator.restore_bag(bag)?,
+            None if saved.config.tile_bag.is_some() => {
+                return Err("Saved game is missing its tile bag".to_string())
+            }
+            None => {}
+        }
+        let mask = board_mask(&saved.config);
+        let board = Board::from_cells(saved.cells, mask, generator, rule);
+        let mut game = Game::from_board(saved.config, board, saved.score, saved.moves);
+        game.undos = saved.undos;
+        game.milestones = saved.milestones;
+        game.record_milestones();
+        game.history = vec![game.snapshot()];
+        Ok(game)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn save_json(&self) -> Result<String, String> {
+        serde_json::to_string(&self.to_saved())
+            .map_err(|err| format!("Cannot serialize game: {}", err))
+    }
+}
+
+impl<R: SnapshotGenerator + Clone> Game<R> {
+    pub fn from_saved(saved: SavedGame, rng: R) -> Result<Game<R>, String> {
+        Game::from_saved_with_rule(saved, rng, ClassicMerge)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn load_json(json: &str, rng: R) -> Result<Game<R>, String> {
+        let saved: SavedGame =
+            serde_json::from_str(json).map_err(|err| format!("Invalid saved game: {}", err))?;
+        Game::from_saved(saved, rng)
+    }
+}
+
+fn validate_config(config: &GameConfig) -> Result<(), String> {
+    if !(MIN_SIZE..=MAX_SIZE).contains(&config.rows) {
+        return Err(format!(
+            "Board should have between {} and {} rows. Actual rows: {}",
+            MIN_SIZE, MAX_SIZE, config.rows
+        ));
+    }
+    if !(MIN_SIZE..=MAX_SIZE).contains(&config.cols) {
+        return Err(format!(
+            "Board should have between {} and {} columns. Actual columns: {}",
+            MIN_SIZE, MAX_SIZE, config.cols
+        ));
+    }
+    if let Some(mask) = &config.mask {
+        if mask.len() != config.rows || mask.iter().any(|row| row.len() != config.cols) {
+            return Err(format!(
+                "Mask should have {} rows of {} cells",
+                config.rows, config.cols
+            ));
+        }
+    }
+    let active_cells = match &config.mask {
+        Some(mask) => mask.iter().flatten().filter(|active| **active).count(),
+        None => config.rows * config.cols,
+    };
+    for (index, (i, j)) in config.walls.iter().enumerate() {
+        if *i >= config.rows || *j >= config.cols {
+            return Err(format!(
+                "Wall ({}, {}) is outside the {}x{} board",
+                i, j, config.rows, config.cols
+            ));
+        }
+        if config.walls[..index].contains(&(*i, *j)) {
+            return Err(format!("Wall ({}, {}) is listed twice", i, j));
+        }
+        if config.mask.as_ref().is_some_and(|mask| !mask[*i][*j]) {
+            return Err(format!("Wall ({}, {}) is outside the board shape", i, j));
+        }
+    }
+    let free_cells = active_cells - config.walls.len();
+    if free_cells == 0 {
+        return Err("Board should have at least one free cell".to_string());
+    }
+    if config.initial_tiles > free_cells {
+        return Err(format!(
+            "Cannot place {} initial tiles on a board with {} free cells",
+            config.initial_tiles, free_cells
+        ));
+    }
+    if config.win_target <= 0 {
+        return Err(format!(
+            "Win target should be positive. Actual target: {}",
+            config.win_target
+        ));
+    }
+    if config.undo_penalty < 0 {
+        return Err(format!(
+            "Undo penalty should not be negative. Actual penalty: {}",
+            config.undo_penalty
+        ));
+    }
+    if let Some(option) = config.tile_options.iter().find(|option| option.value <= 0) {
+        return Err(format!(
+            "Tile options should spawn positive tiles. Actual value: {}",
+            option.value
+        ));
+    }
+    Ok(())
+}
+
+fn validate_cells<M: MergeRule>(
+    config: &GameConfig,
+    cells: &[Vec<i32>],
+    rule: &M,
+) -> Result<(), String> {
+    if cells.len() != config.rows {
+        return Err(format!(
+            "Board should have {} rows. Actual rows: {}",
+            config.rows,
+            cells.len()
+        ));
+    }
+    for (i, row) in cells.iter().enumerate() {
+        if row.len() != config.cols {
+            return Err(format!(
+                "Row {} should have {} cells. Actual cells: {}",
+                i,
+                config.cols,
+                row.len()
+            ));
+        }
+        for (j, cell) in row.iter().enumerate() {
+            if *cell != 0 && config.mask.as_ref().is_some_and(|mask| !mask[i][j]) {
+                return Err(format!(
+                    "Cell ({}, {}) is outside the board shape but has value {}",
+                    i, j, cell
+                ));
+            }
+            if (*cell == WALL) != config.walls.contains(&(i, j)) {
+                return Err(format!(
+                    "Cell ({}, {}) does not match the configured walls",
+                    i, j
+                ));
+            }
+            if *cell != 0
+                && *cell != WALL
+                && !config
+                    .tile_options
+                    .iter()
+                    .any(|o| rule.can_build(*cell, o.value))
+            {
+                return Err(format!(
+                    "Cell ({}, {}) has value {}, which cannot be made from the configured tiles",
+                    i, j, cell
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn board_mask(config: &GameConfig) -> Matrix<bool> {
+    match &config.mask {
+        Some(mask) => Matrix::new(mask.clone()),
+        None => Matrix::filled(config.rows, config.cols, true),
+    }
+}
+
+fn create_generator<R: RandomNumberGenerator>(
+    config: &GameConfig,
+    rng: R,
+) -> Result<TileGenerator<R>, String> {
+    let generator = TileGenerator::new(config.tile_options.clone(), rng)?;
+    match &config.tile_bag {
+        Some(tiles) => generator.with_bag(tiles.clone()),
+        None => Ok(generator),
+    }
+}
+
+fn max_tile(cells: &Matrix<i32>) -> i32 {
+    cells.row_iter().flatten().copied().max().unwrap_or(0)
+}
+
+#[cfg(test)]
+fn first_cell_generator() -> crate::random::MockRandomNumberGenerator {
+    let mut rng = crate::random::MockRandomNumberGenerator::new();
+    rng.expect_next_in_range().returning(|range| range.start);
+    rng.expect_next_float().return_const(0.0);
+    rng.expect_clone().returning(first_cell_generator);
+    rng
+}
+
+#[test]
+fn test_new_game() {
+    let game = Game::new(GameConfig::default(), first_cell_generator()).unwrap();
+    assert_eq!(
+        game.cells(),
+        &[
+            vec![2, 2, 0, 0],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+        ][..]
+    );
+    assert_eq!(game.score(), 0);
+    assert_eq!(game.moves(), 0);
+    assert!(!game.is_over());
+}
+
+#[test]
+fn test_new_game_invalid_config() {
+    let small = GameConfig {
+        rows: 1,
+        ..GameConfig::default()
+    };
+    assert!(Game::new(small, first_cell_generator()).is_err());
+
+    let unreachable = GameConfig {
+        win_target: 0,
+        ..GameConfig::default()
+    };
+    assert!(Game::new(unreachable, first_cell_generator()).is_err());
+
+    let crowded = GameConfig {
+        rows: 2,
+        cols: 2,
+        initial_tiles: 5,
+        ..GameConfig::default()
+    };
+    assert!(Game::new(crowded, first_cell_generator()).is_err());
+
+    let rewarding_undo = GameConfig {
+        undo_penalty: -1,
+        ..GameConfig::default()
+    };
+    assert!(Game::new(rewarding_undo, first_cell_generator()).is_err());
+
+    let too_wide = GameConfig {
+        cols: 17,
+        ..GameConfig::default()
+    };
+    assert_eq!(
+        Game::new(too_wide, first_cell_generator()).err().unwrap(),
+        "Board should have between 2 and 16 columns. Actual columns: 17"
+    );
+
+    let invalid_probabilities = GameConfig {
+        tile_options: vec![TileOption {
+            value: 2,
+            probability: 50,
+        }],
+        ..GameConfig::default()
+    };
+    assert!(Game::new(invalid_probabilities, first_cell_generator()).is_err());
+
+    for value in &[-1, 0] {
+        let non_positive_tiles = GameConfig {
+            tile_options: vec![TileOption {
+                value: *value,
+                probability: 100,
+            }],
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            Game::new(non_positive_tiles, first_cell_generator())
+                .err()
+                .unwrap(),
+            format!(
+                "Tile options should spawn positive tiles. Actual value: {}",
+                value
+            )
+        );
+    }
+}
+
+#[test]
+fn test_non_square_boards() {
+    for (rows, cols) in &[(2, 16), (16, 2), (3, 5), (16, 16)] {
+        let config = GameConfig {
+            rows: *rows,
+            cols: *cols,
+            endless: true,
+            ..GameConfig::default()
+        };
+        let mut game = Game::new(config, crate::random::Pcg32::new(3)).unwrap();
+        while !game.is_over() && game.moves() < 1000 {
+            let moves = game.legal_moves();
+            let direction = moves[game.moves() % moves.len()];
+            assert!(matches!(
+                game.make_move(direction),
+                MoveOutcome::Moved { .. }
+            ));
+        }
+        assert_eq!((game.cells().rows(), game.cells().cols()), (*rows, *cols));
+        if rows * cols <= 16 {
+            assert_eq!(game.state(), GameState::Lost);
+            assert!(game.cells().row_iter().flatten().all(|cell| *cell != 0));
+        }
+    }
+}
+
+#[test]
+fn test_non_square_game_over() {
+    let config = GameConfig {
+        rows: 2,
+        cols: 3,
+        initial_tiles: 6,
+        ..GameConfig::default()
+    };
+    let mut rng = crate::random::MockRandomNumberGenerator::new();
+    rng.expect_next_in_range().returning(|range| range.start);
+    let mut floats = vec![0.0, 0.95, 0.0, 0.95, 0.0, 0.95].into_iter();
+    rng.expect_next_float()
+        .returning(move || floats.next().unwrap());
+    rng.expect_clone().returning(first_cell_generator);
+    let game = Game::new(config, rng).unwrap();
+
+    assert_eq!(game.cells(), &[vec![2, 4, 2], vec![4, 2, 4]][..]);
+    assert_eq!(game.state(), GameState::Lost);
+}
+
+#[cfg(test)]
+#[derive(Clone)]
+struct MaxMerge;
+
+#[cfg(test)]
+impl MergeRule for MaxMerge {
+    fn can_merge(&self, _first: i32, _second: i32) -> bool {
+        true
+    }
+
+    fn merge(&self, first: i32, second: i32) -> i32 {
+        first.max(second)
+    }
+
+    fn score(&self, _first: i32, _second: i32) -> i32 {
+        1
+    }
+}
+
+#[test]
+fn test_custom_merge_rule() {
+    let mut game =
+        Game::with_rule(GameConfig::default(), first_cell_generator(), MaxMerge).unwrap();
+    assert_eq!(
+        game.make_move(Direction::Right),
+        MoveOutcome::Moved {
+            score: 1,
+            spawned: Some((0, 0, 2)),
+        }
+    );
+    assert_eq!(game.cells()[0], [2, 0, 0, 2]);
+    assert_eq!(game.score(), 1);
+
+    use crate::random::Pcg32;
+    let mut saved = saved_game_after_moves(0);
+    saved.cells[1][1] = 6;
+    assert!(Game::from_saved(saved.clone(), Pcg32::new(0)).is_err());
+    let game = Game::from_saved_with_rule(saved, Pcg32::new(0), MaxMerge).unwrap();
+    assert_eq!(game.cells()[1][1], 6);
+}
+
+#[test]
+fn test_fibonacci_game() {
+    use crate::merge_rule::FibonacciMerge;
+
+    let mut game = Game::with_rule(
+        GameConfig::fibonacci(),
+        first_cell_generator(),
+        FibonacciMerge,
+    )
+    .unwrap();
+    assert_eq!(game.cells()[0], [1, 1, 0, 0]);
+    assert_eq!(
+        game.make_move(Direction::Left),
+        MoveOutcome::Moved {
+            score: 2,
+            spawned: Some((0, 1, 1)),
+        }
+    );
+    assert_eq!(
+        game.make_move(Direction::Left),
+        MoveOutcome::Moved {
+            score: 3,
+            spawned: Some((0, 1, 1)),
+        }
+    );
+    assert_eq!(game.cells()[0], [3, 1, 0, 0]);
+
+    let mut saved = saved_game_after_moves(0);
+    saved.config = GameConfig::fibonacci();
+    saved.cells = vec![
+        vec![1, 3, 8, 21],
+        vec![3, 8, 21, 55],
+        vec![8, 21, 55, 144],
+        vec![21, 55, 144, 2],
+    ];
+    assert!(Game::from_saved(saved.clone(), crate::random::Pcg32::new(0)).is_err());
+    let game =
+        Game::from_saved_with_rule(saved.clone(), crate::random::Pcg32::new(0), FibonacciMerge)
+            .unwrap();
+    assert_eq!(game.state(), GameState::Lost);
+
+    saved.cells[3][3] = 89;
+    let game =
+        Game::from_saved_with_rule(saved.clone(), crate::random::Pcg32::new(0), FibonacciMerge)
+            .unwrap();
+    assert_eq!(game.legal_moves().len(), 4);
+    assert_eq!(game.state(), GameState::InProgress);
+
+    saved.cells[3][3] = 4181;
+    saved.config.endless = true;
+    let game =
+        Game::from_saved_with_rule(saved, crate::random::Pcg32::new(0), FibonacciMerge).unwrap();
+    let tiles: Vec<i32> = game.milestones().iter().map(|m| m.tile).collect();
+    assert_eq!(tiles, vec![2584, 4181]);
+}
+
+#[test]
+fn test_threes_game() {
+    use crate::merge_rule::ThreesMerge;
+    use crate::random::Pcg32;
+
+    let mut game = Game::with_rule(GameConfig::threes(), Pcg32::new(9), ThreesMerge).unwrap();
+    let tiles = game.cells().row_iter().flatten().filter(|cell| **cell != 0);
+    assert_eq!(tiles.count(), 9);
+    for _ in 0..30 {
+        let moves = game.legal_moves();
+        if moves.is_empty() {
+            break;
+        }
+        let direction = moves[game.moves() % moves.len()];
+        let next = game.next_tile().unwrap();
+        assert!((1..=3).contains(&next));
+        match game.make_move(direction) {
+            MoveOutcome::Moved {
+                spawned: Some((i, j, tile)),
+                ..
+            } => {
+                assert_eq!(tile, next);
+                let edge = match direction {
+                    Direction::Left => j == 3,
+                    Direction::Right => j == 0,
+                    Direction::Up => i == 3,
+                    Direction::Down => i == 0,
+                };
+                assert!(edge);
+            }
+            outcome => panic!("Unexpected outcome {:?}", outcome),
+        }
+    }
+
+    let saved = game.to_saved();
+    assert!(saved.bag.is_some());
+    let mut loaded = Game::from_saved_with_rule(saved.clone(), Pcg32::new(0), ThreesMerge).unwrap();
+    assert_eq!(loaded.next_tile(), game.next_tile());
+    for _ in 0..10 {
+        if let Some(direction) = game.legal_moves().first().copied() {
+            assert_eq!(game.make_move(direction), loaded.make_move(direction));
+        }
+    }
+    assert_eq!(loaded.cells(), game.cells());
+
+    let missing_bag = SavedGame { bag: None, ..saved };
+    assert!(Game::from_saved_with_rule(missing_bag, Pcg32::new(0), ThreesMerge).is_err());
+}
+
+#[test]
+fn test_powers_of_three_game() {
+    use crate::merge_rule::BaseMerge;
+
+    assert_eq!(GameConfig::powers_of(2).unwrap().win_target, 2048);
+    assert_eq!(GameConfig::powers_of(3000).unwrap().win_target, 3000);
+    assert!(GameConfig::powers_of(1).is_err());
+    assert!(GameConfig::powers_of(100_000).is_err());
+    let config = GameConfig {
+        initial_tiles: 3,
+        ..GameConfig::powers_of(3).unwrap()
+    };
+    assert_eq!(config.win_target, 2187);
+    assert_eq!(config.tile_options[1].value, 9);
+    let rule = BaseMerge::new(3).unwrap();
+    let mut game = Game::with_rule(config, first_cell_generator(), rule).unwrap();
+    assert_eq!(game.cells()[0], [3, 3, 3, 0]);
+    assert_eq!(
+        game.make_move(Direction::Right),
+        MoveOutcome::Moved {
+            score: 9,
+            spawned: Some((0, 0, 3)),
+        }
+    );
+    assert_eq!(game.cells()[0], [3, 0, 0, 9]);
+
+    let mut saved = saved_game_after_moves(0);
+    saved.config = GameConfig::powers_of(3).unwrap();
+    saved.cells = vec![
+        vec![3, 3, 9, 9],
+        vec![9, 9, 3, 3],
+        vec![3, 3, 9, 9],
+        vec![9, 9, 3, 3],
+    ];
+    let game = Game::from_saved_with_rule(saved, crate::random::Pcg32::new(0), rule).unwrap();
+    assert_eq!(game.state(), GameState::Lost);
+}
+
+#[test]
+fn test_walls() {
+    use crate::random::Pcg32;
+
+    let config = GameConfig {
+        rows: 3,
+        cols: 3,
+        walls: vec![(0, 1), (1, 1), (2, 1)],
+        initial_tiles: 6,
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config.clone(), first_cell_generator()).unwrap();
+    assert_eq!(
+        game.cells(),
+        &[vec![2, -1, 2], vec![2, -1, 2], vec![2, -1, 2]][..]
+    );
+    assert_eq!(game.legal_moves(), vec![Direction::Up, Direction::Down]);
+    assert_eq!(
+        game.make_move(Direction::Up),
+        MoveOutcome::Moved {
+            score: 8,
+            spawned: Some((2, 0, 2)),
+        }
+    );
+    assert_eq!(
+        game.cells(),
+        &[vec![4, -1, 4], vec![2, -1, 2], vec![2, -1, 0]][..]
+    );
+
+    let mut game = Game::new(config.clone(), Pcg32::new(4)).unwrap();
+    while let Some(direction) = game.legal_moves().first().copied() {
+        game.make_move(direction);
+        for (i, j) in &config.walls {
+            assert_eq!(game.cells()[*i][*j], WALL);
+        }
+    }
+
+    let mut saved = game.to_saved();
+    assert!(Game::from_saved(saved.clone(), Pcg32::new(0)).is_ok());
+    saved.cells[0][1] = 0;
+    assert!(Game::from_saved(saved.clone(), Pcg32::new(0)).is_err());
+    saved.config.walls[0] = (0, 0);
+    assert!(Game::from_saved(saved, Pcg32::new(0)).is_err());
+
+    let outside = GameConfig {
+        walls: vec![(4, 0)],
+        ..GameConfig::default()
+    };
+    assert_eq!(
+        Game::new(outside, Pcg32::new(0)).err().unwrap(),
+        "Wall (4, 0) is outside the 4x4 board"
+    );
+    let twice = GameConfig {
+        walls: vec![(1, 1), (1, 1)],
+        ..GameConfig::default()
+    };
+    assert!(Game::new(twice, Pcg32::new(0)).is_err());
+    let crowded = GameConfig {
+        rows: 2,
+        cols: 2,
+        walls: vec![(0, 0), (1, 1)],
+        initial_tiles: 3,
+        ..GameConfig::default()
+    };
+    assert!(Game::new(crowded, Pcg32::new(0)).is_err());
+}
+
+#[test]
+fn test_masked_board() {
+    use crate::random::Pcg32;
+
+    let config = GameConfig {
+        mask: Some(crate::shape::ring(4)),
+        initial_tiles: 4,
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config.clone(), first_cell_generator()).unwrap();
+    assert_eq!(
+        game.cells(),
+        &[
+            vec![2, 2, 2, 2],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+        ][..]
+    );
+    assert_eq!(
+        game.make_move(Direction::Down),
+        MoveOutcome::Moved {
+            score: 0,
+            spawned: Some((0, 0, 2)),
+        }
+    );
+    assert_eq!(
+        game.cells(),
+        &[
+            vec![2, 0, 0, 0],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+            vec![2, 2, 2, 2],
+        ][..]
+    );
+    game.make_move(Direction::Left);
+    assert_eq!(game.cells()[3], [4, 4, 0, 0]);
+    assert!(!game.mask()[1][1]);
+
+    let mut game = Game::new(config.clone(), Pcg32::new(12)).unwrap();
+    while let Some(direction) = game.legal_moves().first().copied() {
+        game.make_move(direction);
+        assert!(game
+            .cells()
+            .row_iter()
+            .zip(game.mask().row_iter())
+            .all(|(row, active)| row
+                .iter()
+                .zip(active)
+                .all(|(cell, active)| *active || *cell == 0)));
+    }
+    assert_eq!(game.state(), GameState::Lost);
+
+    let mut saved = game.to_saved();
+    saved.cells[1][1] = 2;
+    assert_eq!(
+        Game::from_saved(saved, Pcg32::new(0)).err().unwrap(),
+        "Cell (1, 1) is outside the board shape but has value 2"
+    );
+    let wrong_size = GameConfig {
+        mask: Some(crate::shape::ring(5)),
+        ..GameConfig::default()
+    };
+    assert!(Game::new(wrong_size, Pcg32::new(0)).is_err());
+    let wall_in_hole = GameConfig {
+        walls: vec![(1, 2)],
+        ..config.clone()
+    };
+    assert!(Game::new(wall_in_hole, Pcg32::new(0)).is_err());
+    let crowded = GameConfig {
+        initial_tiles: 13,
+        ..config
+    };
+    assert!(Game::new(crowded, Pcg32::new(0)).is_err());
+    let empty = GameConfig {
+        mask: Some(vec![vec![false; 4]; 4]),
+        initial_tiles: 0,
+        ..GameConfig::default()
+    };
+    assert_eq!(
+        Game::new(empty, Pcg32::new(0)).err().unwrap(),
+        "Board should have at least one free cell"
+    );
+    let single = GameConfig {
+        mask: Some(vec![
+            vec![true, false, false, false],
+            vec![false; 4],
+            vec![false; 4],
+            vec![false; 4],
+        ]),
+        ..GameConfig::default()
+    };
+    assert_eq!(
+        Game::new(single, Pcg32::new(0)).err().unwrap(),
+        "Cannot place 2 initial tiles on a board with 1 free cells"
+    );
+}
+
+#[test]
+fn test_wraparound_game() {
+    use crate::random::Pcg32;
+
+    let mut saved = saved_game_after_moves(0);
+    saved.cells = vec![
+        vec![2, 4, 8, 2],
+        vec![4, 8, 16, 4],
+        vec![8, 16, 32, 8],
+        vec![2, 4, 8, 2],
+    ];
+    let game = Game::from_saved(saved.clone(), Pcg32::new(0)).unwrap();
+    assert_eq!(game.state(), GameState::Lost);
+
+    saved.config.movement = Movement::Wrap;
+    let mut game = Game::from_saved(saved, Pcg32::new(0)).unwrap();
+    assert_eq!(game.legal_moves().len(), 4);
+    match game.make_move(Direction::Left) {
+        MoveOutcome::Moved { score, .. } => assert_eq!(score, 32),
+        outcome => panic!("Unexpected outcome {:?}", outcome),
+    }
+    assert_eq!(game.cells()[0][..3], [4, 4, 8]);
+    assert_eq!(game.cells()[2][..3], [16, 16, 32]);
+}
+
+#[test]
+fn test_make_move() {
+    let mut game = Game::new(GameConfig::default(), first_cell_generator()).unwrap();
+
+    assert_eq!(
+        game.make_move(Direction::Right),
+        MoveOutcome::Moved {
+            score: 4,
+            spawned: Some((0, 0, 2)),
+        }
+    );
+    assert_eq!(game.score(), 4);
+    assert_eq!(game.moves(), 1);
+    assert_eq!(
+        game.cells(),
+        &[
+            vec![2, 0, 0, 4],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+        ][..]
+    );
+}
+
+#[test]
+fn test_is_over() {
+    let config = GameConfig {
+        rows: 2,
+        cols: 2,
+        initial_tiles: 4,
+        ..GameConfig::default()
+    };
+    let mut rng = crate::random::MockRandomNumberGenerator::new();
+    rng.expect_next_in_range().returning(|range| range.start);
+    let mut floats = vec![0.0, 0.95, 0.95, 0.0].into_iter();
+    rng.expect_next_float()
+        .returning(move || floats.next().unwrap());
+    rng.expect_clone().returning(first_cell_generator);
+    let mut game = Game::new(config, rng).unwrap();
+
+    assert_eq!(game.cells(), &[vec![2, 4], vec![4, 2]][..]);
+    assert_eq!(game.state(), GameState::Lost);
+    assert!(game.is_over());
+    assert_eq!(game.legal_moves(), vec![]);
+    assert_eq!(game.make_move(Direction::Left), MoveOutcome::GameOver);
+}
+
+#[test]
+fn test_win_target() {
+    let config = GameConfig {
+        win_target: 8,
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config, first_cell_generator()).unwrap();
+    assert_eq!(game.state(), GameState::InProgress);
+
+    game.make_move(Direction::Right);
+    game.make_move(Direction::Right);
+    game.make_move(Direction::Right);
+    assert_eq!(
+        game.cells(),
+        &[
+            vec![2, 0, 4, 4],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+        ][..]
+    );
+    assert_eq!(game.state(), GameState::InProgress);
+
+    game.make_move(Direction::Right);
+    assert_eq!(game.max_tile(), 8);
+    assert_eq!(game.state(), GameState::Won);
+    assert!(game.is_over());
+    assert_eq!(game.milestones(), &[Milestone { tile: 8, moves: 4 }][..]);
+}
+
+#[test]
+fn test_endless_milestones() {
+    let config = GameConfig {
+        win_target: 4,
+        endless: true,
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config, first_cell_generator()).unwrap();
+
+    for _ in 0..4 {
+        game.make_move(Direction::Right);
+        assert_eq!(game.state(), GameState::InProgress);
+    }
+    assert!(!game.is_over());
+    assert_eq!(
+        game.milestones(),
+        &[
+            Milestone { tile: 4, moves: 1 },
+            Milestone { tile: 8, moves: 4 },
+        ][..]
+    );
+}
+
+#[test]
+fn test_illegal_move_does_not_spawn() {
+    let mut game = Game::new(GameConfig::default(), first_cell_generator()).unwrap();
+    game.make_move(Direction::Right);
+    assert_eq!(
+        game.legal_moves(),
+        vec![Direction::Left, Direction::Right, Direction::Down]
+    );
+
+    assert_eq!(game.make_move(Direction::Up), MoveOutcome::Illegal);
+    assert_eq!(game.moves(), 1);
+    assert_eq!(
+        game.cells(),
+        &[
+            vec![2, 0, 0, 4],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+        ][..]
+    );
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut game = Game::new(GameConfig::default(), first_cell_generator()).unwrap();
+    assert!(!game.can_undo());
+    assert!(game.undo().is_err());
+
+    game.make_move(Direction::Right);
+    game.make_move(Direction::Down);
+    let after_two_moves = game.cells().to_vec();
+    assert_eq!(game.score(), 4);
+
+    assert!(game.undo().is_ok());
+    assert_eq!(game.moves(), 1);
+    assert_eq!(
+        game.cells(),
+        &[
+            vec![2, 0, 0, 4],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 0, 0],
+        ][..]
+    );
+    assert!(game.undo().is_ok());
+    assert_eq!(game.cells()[0], vec![2, 2, 0, 0]);
+    assert_eq!(game.score(), 0);
+    assert!(game.can_redo());
+
+    assert!(game.redo().is_ok());
+    assert!(game.redo().is_ok());
+    assert!(game.redo().is_err());
+    assert_eq!(game.cells(), &after_two_moves[..]);
+    assert_eq!(game.score(), 4);
+    assert_eq!(game.undos(), 2);
+}
+
+#[test]
+fn test_move_after_undo_discards_redo() {
+    let mut game = Game::new(GameConfig::default(), first_cell_generator()).unwrap();
+    game.make_move(Direction::Right);
+    game.make_move(Direction::Down);
+    game.undo().unwrap();
+
+    game.make_move(Direction::Left);
+    assert_eq!(game.moves(), 2);
+    assert_eq!(
+        game.move_history(),
+        &[Direction::Right, Direction::Left][..]
+    );
+    assert!(!game.can_redo());
+    assert!(game.jump_to(3).is_err());
+}
+
+#[test]
+fn test_jump_to() {
+    let mut game = Game::new(GameConfig::default(), first_cell_generator()).unwrap();
+    for _ in 0..4 {
+        game.make_move(Direction::Right);
+    }
+    let final_cells = game.cells().to_vec();
+
+    assert!(game.jump_to(1).is_ok());
+    assert_eq!(game.moves(), 1);
+    assert_eq!(game.score(), 4);
+    assert_eq!(game.undos(), 3);
+
+    assert!(game.jump_to(4).is_ok());
+    assert_eq!(game.cells(), &final_cells[..]);
+    assert_eq!(game.undos(), 3);
+    assert!(game.jump_to(5).is_err());
+}
+
+#[test]
+fn test_undo_limit_and_penalty() {
+    let config = GameConfig {
+        undo_limit: Some(2),
+        undo_penalty: 1,
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config, first_cell_generator()).unwrap();
+    for _ in 0..4 {
+        game.make_move(Direction::Right);
+    }
+    assert_eq!(game.score(), 16);
+
+    assert!(game.jump_to(2).is_ok());
+    assert_eq!(game.score(), 2);
+    assert!(!game.can_undo());
+    assert!(game.undo().is_err());
+    assert_eq!(game.moves(), 2);
+
+    assert!(game.redo().is_ok());
+    assert_eq!(game.score(), 6);
+
+    let config = GameConfig {
+        undo_penalty: i32::MAX,
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config, first_cell_generator()).unwrap();
+    game.make_move(Direction::Right);
+    game.make_move(Direction::Right);
+    assert!(game.undo().is_ok());
+    assert!(game.undo().is_ok());
+    assert_eq!(game.score(), 0);
+    assert!(game.redo().is_ok());
+    assert_eq!(game.score(), 0);
+}
+
+#[test]
+fn test_seeded_games_are_reproducible() {
+    use crate::random::Pcg32;
+
+    let mut first = Game::new(GameConfig::default(), Pcg32::new(99)).unwrap();
+    let mut second = Game::new(GameConfig::default(), Pcg32::new(99)).unwrap();
+    for direction in [
+        Direction::Left,
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+    ]
+    .iter()
+    .cycle()
+    .take(40)
+    {
+        assert_eq!(first.make_move(*direction), second.make_move(*direction));
+    }
+    assert_eq!(first.cells(), second.cells());
+    assert_eq!(first.score(), second.score());
+}
+
+#[test]
+fn test_restore_rng_state() {
+    use crate::random::Pcg32;
+
+    let mut game = Game::new(GameConfig::default(), Pcg32::new(5)).unwrap();
+    let state = game.rng_state().to_string();
+    let expected: Vec<MoveOutcome> = [Direction::Left, Direction::Up, Direction::Right]
+        .iter()
+        .map(|direction| game.make_move(*direction))
+        .collect();
+
+    let mut restored = Game::new(GameConfig::default(), Pcg32::new(5)).unwrap();
+    restored
+        .restore_rng_state(&Pcg32::new(77).save_state())
+        .unwrap();
+    restored.restore_rng_state(&state.parse().unwrap()).unwrap();
+    let actual: Vec<MoveOutcome> = [Direction::Left, Direction::Up, Direction::Right]
+        .iter()
+        .map(|direction| restored.make_move(*direction))
+        .collect();
+    assert_eq!(actual, expected);
+}
+
+#[cfg(test)]
+fn saved_game_after_moves(moves: usize) -> SavedGame {
+    use crate::random::Pcg32;
+
+    let mut game = Game::new(GameConfig::default(), Pcg32::new(3)).unwrap();
+    for direction in crate::board::DIRECTIONS.iter().cycle().take(moves) {
+        game.make_move(*direction);
+    }
+    game.to_saved()
+}
+
+#[test]
+fn test_saved_game_round_trip() {
+    use crate::random::Pcg32;
+
+    let mut game = Game::new(GameConfig::default(), Pcg32::new(3)).unwrap();
+    for direction in crate::board::DIRECTIONS.iter().cycle().take(12) {
+        game.make_move(*direction);
+    }
+    let saved = game.to_saved();
+    let mut loaded = Game::from_saved(saved.clone(), Pcg32::new(0)).unwrap();
+    assert_eq!(loaded.to_saved(), saved);
+    assert_eq!(loaded.moves(), game.moves());
+    assert!(!loaded.can_undo());
+    assert!(loaded.undo().is_err());
+
+    for direction in crate::board::DIRECTIONS.iter().rev().cycle().take(12) {
+        assert_eq!(loaded.make_move(*direction), game.make_move(*direction));
+    }
+    assert_eq!(loaded.cells(), game.cells());
+    assert_eq!(loaded.score(), game.score());
+    assert!(loaded.jump_to(saved.moves).is_ok());
+    assert_eq!(loaded.cells(), &saved.cells[..]);
+}
+
+#[test]
+fn test_from_saved_rejects_malformed_boards() {
+    use crate::random::Pcg32;
+
+    let saved = saved_game_after_moves(6);
+
+    let mut missing_row = saved.clone();
+    missing_row.cells.pop();
+    let err = Game::from_saved(missing_row, Pcg32::new(0)).err().unwrap();
+    assert_eq!(err, "Board should have 4 rows. Actual rows: 3");
+
+    let mut short_row = saved.clone();
+    short_row.cells[2].pop();
+    let err = Game::from_saved(short_row, Pcg32::new(0)).err().unwrap();
+    assert_eq!(err, "Row 2 should have 4 cells. Actual cells: 3");
+
+    let mut odd_tile = saved.clone();
+    odd_tile.cells[1][3] = 6;
+    let err = Game::from_saved(odd_tile, Pcg32::new(0)).err().unwrap();
+    assert_eq!(
+        err,
+        "Cell (1, 3) has value 6, which cannot be made from the configured tiles"
+    );
+
+    let mut negative_score = saved.clone();
+    negative_score.score = -4;
+    assert!(Game::from_saved(negative_score, Pcg32::new(0)).is_err());
+
+    let mut too_many_undos = saved.clone();
+    too_many_undos.config.undo_limit = Some(1);
+    too_many_undos.undos = 3;
+    let err = Game::from_saved(too_many_undos, Pcg32::new(0))
+        .err()
+        .unwrap();
+    assert_eq!(err, "Saved game used 3 undos, but the limit is 1");
+
+    let mut invalid_rng = saved;
+    invalid_rng.rng_state = RngState::new(vec![1]);
+    assert!(Game::from_saved(invalid_rng, Pcg32::new(0)).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_json_round_trip() {
+    use crate::random::Pcg32;
+
+    let saved = saved_game_after_moves(10);
+    let game = Game::from_saved(saved.clone(), Pcg32::new(0)).unwrap();
+    let json = game.save_json().unwrap();
+    let loaded = Game::load_json(&json, Pcg32::new(0)).unwrap();
+    assert_eq!(loaded.to_saved(), saved);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_load_json_rejects_malformed_input() {
+    use crate::random::Pcg32;
+
+    assert!(Game::load_json("{", Pcg32::new(0))
+        .err()
+        .unwrap()
+        .starts_with("Invalid saved game"));
+
+    let game = Game::from_saved(saved_game_after_moves(4), Pcg32::new(0)).unwrap();
+    let mut value: serde_json::Value = serde_json::from_str(&game.save_json().unwrap()).unwrap();
+    value["cells"][0] = serde_json::json!([2, 2, 2]);
+    let err = Game::load_json(&value.to_string(), Pcg32::new(0))
+        .err()
+        .unwrap();
+    assert_eq!(err, "Row 0 should have 4 cells. Actual cells: 3");
+}
@@ -0,0 +1,415 @@
+//! 2048 on a hexagonal board with six move directions.
+//!
+//! `HexGame` is separate from `game::Game` because `Direction`, the bitboard,
+//! the agents and the replay format all assume four directions. It shares the
+//! line shifting and merge rules of the square engine, and supports winning,
+//! losing and scoring, but has no undo, saving or replays.
+
+use crate::board::Movement;
+use crate::game::{GameState, MoveOutcome, MAX_SIZE};
+use crate::matrix::Matrix;
+use crate::merge_rule::{ClassicMerge, MergeRule};
+use crate::new_board::{can_shift_values, shift_values};
+use crate::random::RandomNumberGenerator;
+use crate::tile_generator::{TileGenerator, TileOption};
+
+pub const MAX_RADIUS: usize = (MAX_SIZE - 1) / 2;
+
+/// A move on a pointy-top hex board, named after where the tiles go.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HexDirection {
+    East,
+    West,
+    NorthEast,
+    SouthWest,
+    NorthWest,
+    SouthEast,
+}
+
+pub const HEX_DIRECTIONS: [HexDirection; 6] = [
+    HexDirection::East,
+    HexDirection::West,
+    HexDirection::NorthEast,
+    HexDirection::SouthWest,
+    HexDirection::NorthWest,
+    HexDirection::SouthEast,
+];
+
+impl HexDirection {
+    /// The axial (q, r) offset of the neighbouring cell in this direction.
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            HexDirection::East => (1, 0),
+            HexDirection::West => (-1, 0),
+            HexDirection::NorthEast => (1, -1),
+            HexDirection::SouthWest => (-1, 1),
+            HexDirection::NorthWest => (0, -1),
+            HexDirection::SouthEast => (0, 1),
+        }
+    }
+}
+
+/// 2048 on a hexagon of hex cells in axial coordinates (q, r), where both
+/// run from `-radius` to `radius` and cells with |q + r| > radius are not
+/// part of the board. Cells are stored at row `r + radius`, column
+/// `q + radius`, which is also how spawned tiles are reported.
+pub struct HexGame<R: RandomNumberGenerator, M: MergeRule = ClassicMerge> {
+    radius: usize,
+    cells: Matrix<i32>,
+    generator: TileGenerator<R>,
+    rule: M,
+    win_target: i32,
+    score: i32,
+    moves: usize,
+}
+
+impl<R: RandomNumberGenerator> HexGame<R> {
+    pub fn new(
+        radius: usize,
+        tile_options: Vec<TileOption>,
+        initial_tiles: usize,
+        rng: R,
+    ) -> Result<HexGame<R>, String> {
+        HexGame::with_rule(radius, tile_options, initial_tiles, rng, ClassicMerge)
+    }
+}
+
+impl<R: RandomNumberGenerator, M: MergeRule> HexGame<R, M> {
+    pub fn with_rule(
+        radius: usize,
+        tile_options: Vec<TileOption>,
+        initial_tiles: usize,
+        rng: R,
+        rule: M,
+    ) -> Result<HexGame<R, M>, String> {
+        if !(1..=MAX_RADIUS).contains(&radius) {
+            return Err(format!(
+                "Hex board radius should be between 1 and {}. Actual radius: {}",
+                MAX_RADIUS, radius
+            ));
+        }
+        let cell_count = 3 * radius * (radius + 1) + 1;
+        if initial_tiles > cell_count {
+            return Err(format!(
+                "Cannot place {} initial tiles on a hex board with {} cells",
+                initial_tiles, cell_count
+            ));
+        }
+        let size = 2 * radius + 1;
+        let mut game = HexGame {
+            radius,
+            cells: Matrix::filled(size, size, 0),
+            generator: TileGenerator::new(tile_options, rng)?,
+            rule,
+            win_target: 2048,
+            score: 0,
+            moves: 0,
+        };
+        for _ in 0..initial_tiles {
+            game.add_random_tile();
+        }
+        Ok(game)
+    }
+
+    /// Replaces the default win target of 2048.
+    pub fn with_win_target(mut self, win_target: i32) -> Result<HexGame<R, M>, String> {
+        if win_target <= 0 {
+            return Err(format!(
+                "Win target should be positive. Actual target: {}",
+                win_target
+            ));
+        }
+        self.win_target = win_target;
+        Ok(self)
+    }
+
+    pub fn win_target(&self) -> i32 {
+        self.win_target
+    }
+
+    pub fn radius(&self) -> usize {
+        self.radius
+    }
+
+    pub fn cells(&self) -> &Matrix<i32> {
+        &self.cells
+    }
+
+    pub fn contains(&self, q: i32, r: i32) -> bool {
+        let radius = self.radius as i32;
+        q.abs() <= radius && r.abs() <= radius && (q + r).abs() <= radius
+    }
+
+    pub fn get(&self, q: i32, r: i32) -> Option<i32> {
+        if !self.contains(q, r) {
+            return None;
+        }
+        let (i, j) = self.position(q, r);
+        Some(self.cells[i][j])
+    }
+
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    pub fn moves(&self) -> usize {
+        self.moves
+    }
+
+    pub fn max_tile(&self) -> i32 {
+        self.cells.row_iter().flatten().copied().max().unwrap_or(0)
+    }
+
+    pub fn legal_moves(&self) -> Vec<HexDirection> {
+        HEX_DIRECTIONS
+            .iter()
+            .copied()
+            .filter(|direction| {
+                self.lines(*direction).iter().any(|line| {
+                    let values: Vec<i32> = line.iter().map(|(i, j)| self.cells[*i][*j]).collect();
+                    can_shift_values(&values, Movement::Slide, &self.rule)
+                })
+            })
+            .collect()
+    }
+
+    pub fn state(&self) -> GameState {
+        if self.max_tile() >= self.win_target {
+            GameState::Won
+        } else if self.legal_moves().is_empty() {
+            GameState::Lost
+        } else {
+            GameState::InProgress
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.state() != GameState::InProgress
+    }
+
+    pub fn make_move(&mut self, direction: HexDirection) -> MoveOutcome {
+        if self.is_over() {
+            return MoveOutcome::GameOver;
+        }
+        if !self.legal_moves().contains(&direction) {
+            return MoveOutcome::Illegal;
+        }
+        let mut score = 0;
+        for line in self.lines(direction) {
+            let mut values: Vec<i32> = line.iter().map(|(i, j)| self.cells[*i][*j]).collect();
+            score += shift_values(&mut values, Movement::Slide, &self.rule);
+            for ((i, j), value) in line.into_iter().zip(values) {
+                self.cells[i][j] = value;
+            }
+        }
+        self.score += score;
+        self.moves += 1;
+        let spawned = self.add_random_tile();
+        MoveOutcome::Moved { score, spawned }
+    }
+
+    /// Draws the board as offset rows of hexes, with `.` for empty cells.
+    pub fn render(&self) -> String {
+        let radius = self.radius as i32;
+        let width = self.max_tile().to_string().len().max(1) + 1;
+        let mut text = String::new();
+        for r in -radius..=radius {
+            let mut row = " ".repeat(r.unsigned_abs() as usize * (width + 1) / 2);
+            let cells: Vec<String> = (-radius..=radius)
+                .filter_map(|q| self.get(q, r))
+                .map(|value| match value {
+                    0 => format!("{:>width$}", ".", width = width),
+                    _ => format!("{:>width$}", value, width = width),
+                })
+                .collect();
+            row += &cells.join(" ");
+            text += row.trim_end();
+            text += "\n";
+        }
+        text
+    }
+
+    fn position(&self, q: i32, r: i32) -> (usize, usize) {
+        let radius = self.radius as i32;
+        ((r + radius) as usize, (q + radius) as usize)
+    }
+
+    /// The lines along the axis of `direction`, each starting at the edge
+    /// the tiles move towards.
+    fn lines(&self, direction: HexDirection) -> Vec<Vec<(usize, usize)>> {
+        let radius = self.radius as i32;
+        let (dq, dr) = direction.offset();
+        let mut lines = vec![];
+        for r in -radius..=radius {
+            for q in -radius..=radius {
+                if !self.contains(q, r) || self.contains(q + dq, r + dr) {
+                    continue;
+                }
+                let mut line = vec![];
+                let (mut q, mut r) = (q, r);
+                while self.contains(q, r) {
+                    line.push(self.position(q, r));
+                    q -= dq;
+                    r -= dr;
+                }
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    fn add_random_tile(&mut self) -> Option<(usize, usize, i32)> {
+        let radius = self.radius as i32;
+        let mut empty_cells = vec![];
+        for r in -radius..=radius {
+            for q in -radius..=radius {
+                if self.get(q, r) == Some(0) {
+                    empty_cells.push(self.position(q, r));
+                }
+            }
+        }
+        if empty_cells.is_empty() {
+            return None;
+        }
+        let (i, j, tile) = self.generator.generate_tile(&empty_cells);
+        self.cells[i][j] = tile;
+        Some((i, j, tile))
+    }
+}
+
+#[cfg(test)]
+fn hex_game(radius: usize, tiles: &[(i32, i32, i32)]) -> HexGame<crate::random::Pcg32> {
+    let options = vec![TileOption {
+        value: 2,
+        probability: 100,
+    }];
+    let mut game = HexGame::new(radius, options, 0, crate::random::Pcg32::new(1)).unwrap();
+    for (q, r, value) in tiles {
+        let (i, j) = game.position(*q, *r);
+        game.cells[i][j] = *value;
+    }
+    game
+}
+
+#[test]
+fn test_lines_follow_the_hex_axes() {
+    let game = hex_game(2, &[]);
+    for direction in &HEX_DIRECTIONS {
+        let lines = game.lines(*direction);
+        let lengths: Vec<usize> = lines.iter().map(|line| line.len()).collect();
+        let mut sorted = lengths.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, vec![3, 3, 4, 4, 5]);
+        assert_eq!(lengths.iter().sum::<usize>(), 19);
+    }
+    let east = game.lines(HexDirection::East);
+    assert_eq!(east[0], vec![(0, 4), (0, 3), (0, 2)]);
+    let north_east = game.lines(HexDirection::NorthEast);
+    assert!(north_east.contains(&vec![(0, 4), (1, 3), (2, 2), (3, 1), (4, 0)]));
+}
+
+#[test]
+fn test_hex_moves() {
+    let mut game = hex_game(1, &[(-1, 0, 2), (1, 0, 2), (0, 1, 4)]);
+    let spawned = match game.make_move(HexDirection::East) {
+        MoveOutcome::Moved { score, spawned } => {
+            assert_eq!(score, 4);
+            spawned.unwrap()
+        }
+        outcome => panic!("unexpected outcome {:?}", outcome),
+    };
+    assert_eq!(game.cells()[spawned.0][spawned.1], 2);
+    assert_eq!(game.get(1, 0), Some(4));
+    let tiles = game.cells().row_iter().flatten().filter(|cell| **cell > 0);
+    assert_eq!(tiles.count(), 3);
+    assert_eq!(game.get(1, 1), None);
+    assert_eq!(game.score(), 4);
+    assert_eq!(game.moves(), 1);
+
+    let mut game = hex_game(1, &[(0, -1, 8), (0, 0, 8)]);
+    game.make_move(HexDirection::SouthEast);
+    assert_eq!(game.get(0, 1), Some(16));
+    let mut game = hex_game(1, &[(1, -1, 8), (-1, 1, 8)]);
+    game.make_move(HexDirection::NorthEast);
+    assert_eq!(game.get(1, -1), Some(16));
+    let mut game = hex_game(1, &[(1, -1, 8)]);
+    assert_eq!(
+        game.make_move(HexDirection::NorthEast),
+        MoveOutcome::Illegal
+    );
+}
+
+#[test]
+fn test_hex_game_over() {
+    let full = [
+        (0, -1, 2),
+        (1, -1, 4),
+        (-1, 0, 4),
+        (0, 0, 8),
+        (1, 0, 2),
+        (-1, 1, 2),
+        (0, 1, 4),
+    ];
+    let mut game = hex_game(1, &full);
+    assert_eq!(game.state(), GameState::Lost);
+    assert!(game.is_over());
+    assert_eq!(game.make_move(HexDirection::West), MoveOutcome::GameOver);
+
+    let mut cells = full;
+    cells[4].2 = 8;
+    let game = hex_game(1, &cells);
+    assert_eq!(
+        game.legal_moves(),
+        vec![HexDirection::East, HexDirection::West]
+    );
+    assert!(HexGame::new(0, vec![], 0, crate::random::Pcg32::new(1)).is_err());
+}
+
+#[test]
+fn test_hex_render() {
+    let game = hex_game(1, &[(0, -1, 2), (-1, 0, 16), (0, 1, 4)]);
+    assert_eq!(game.render(), "    2   .\n 16   .   .\n    .   4\n");
+}
+
+#[test]
+fn test_hex_playthrough() {
+    let options = vec![
+        TileOption {
+            value: 2,
+            probability: 90,
+        },
+        TileOption {
+            value: 4,
+            probability: 10,
+        },
+    ];
+    let mut game = HexGame::new(2, options, 2, crate::random::Pcg32::new(7)).unwrap();
+    let mut turn = 0;
+    while !game.is_over() {
+        let moves = game.legal_moves();
+        let outcome = game.make_move(moves[turn % moves.len()]);
+        assert!(matches!(outcome, MoveOutcome::Moved { .. }));
+        turn += 1;
+    }
+    assert_eq!(game.moves(), turn);
+    for (i, row) in game.cells().row_iter().enumerate() {
+        for (j, cell) in row.iter().enumerate() {
+            let (q, r) = (j as i32 - 2, i as i32 - 2);
+            assert!(game.contains(q, r) || *cell == 0);
+        }
+    }
+}
+
+#[test]
+fn test_hex_win() {
+    let mut game = hex_game(1, &[(0, 0, 8), (1, 0, 8)])
+        .with_win_target(16)
+        .unwrap();
+    assert_eq!(game.state(), GameState::InProgress);
+    game.make_move(HexDirection::East);
+    assert_eq!(game.get(1, 0), Some(16));
+    assert_eq!(game.state(), GameState::Won);
+    assert_eq!(game.make_move(HexDirection::West), MoveOutcome::GameOver);
+    assert!(hex_game(1, &[]).with_win_target(0).is_err());
+    assert_eq!(hex_game(1, &[]).win_target(), 2048);
+}
@@ -1,9 +1,27 @@
-mod random;
+pub mod random;
 
-mod board;
+pub mod board;
 
 mod new_board;
 
-mod tile_generator;
+pub mod merge_rule;
 
-mod matrix;
\ No newline at end of file
+pub mod tile_generator;
+
+pub mod matrix;
+
+pub mod shape;
+
+pub mod hex;
+
+pub mod game;
+
+pub mod replay;
+
+pub mod bitboard;
+
+pub mod ai;
+
+pub mod agent;
+
+pub mod tournament;
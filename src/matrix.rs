@@ -1,14 +1,53 @@
+use crate::board::Direction;
+use std::cell::Cell;
+use std::fmt;
 use std::ops::Index;
 use std::ops::IndexMut;
+use std::slice::{ChunksExact, ChunksExactMut};
 
-struct Matrix<T> {
+#[derive(Clone, PartialEq, Eq)]
+pub struct Matrix<T> {
     data: Vec<T>,
     rows: usize,
     cols: usize,
 }
 
+/// A row or column of a matrix, possibly reversed. `Line`s handed out for a
+/// direction start at the edge that tiles move towards.
+#[derive(Clone, Copy)]
+pub struct Line<'a, T> {
+    data: &'a [T],
+    start: usize,
+    step: isize,
+    len: usize,
+}
+
+/// A mutable row or column, laid out like `Line`. The matrix is viewed as
+/// `Cell`s so that the lines of one matrix can be borrowed at the same time,
+/// and cells are read and written by value.
+pub struct LineMut<'a, T> {
+    data: &'a [Cell<T>],
+    start: usize,
+    step: isize,
+    len: usize,
+}
+
+pub struct Lines<'a, T> {
+    matrix: &'a Matrix<T>,
+    direction: Direction,
+    index: usize,
+}
+
+pub struct LinesMut<'a, T> {
+    data: &'a [Cell<T>],
+    rows: usize,
+    cols: usize,
+    direction: Direction,
+    index: usize,
+}
+
 impl<T> Matrix<T> {
-    fn new(matrix_data: Vec<Vec<T>>) -> Matrix<T> {
+    pub fn new(matrix_data: Vec<Vec<T>>) -> Matrix<T> {
         let rows = matrix_data.len();
         let cols = matrix_data[0].len();
         let mut data = vec![];
@@ -20,11 +59,100 @@ impl<T> Matrix<T> {
         Matrix { data, rows, cols }
     }
 
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn row_iter(&self) -> ChunksExact<'_, T> {
+        self.data.chunks_exact(self.cols)
+    }
+
+    pub fn row_iter_mut(&mut self) -> ChunksExactMut<'_, T> {
+        self.data.chunks_exact_mut(self.cols)
+    }
+
+    pub fn column(&self, col: usize) -> Line<'_, T> {
+        self.line(Direction::Up, col)
+    }
+
+    pub fn column_mut(&mut self, col: usize) -> LineMut<'_, T> {
+        self.line_mut(Direction::Up, col)
+    }
+
+    pub fn column_iter(&self) -> Lines<'_, T> {
+        self.lines(Direction::Up)
+    }
+
+    pub fn column_iter_mut(&mut self) -> LinesMut<'_, T> {
+        self.lines_mut(Direction::Up)
+    }
+
+    /// Number of rows for horizontal directions, columns for vertical ones.
+    pub fn line_count(&self, direction: Direction) -> usize {
+        line_count(self.rows, self.cols, direction)
+    }
+
+    pub fn line(&self, direction: Direction, index: usize) -> Line<'_, T> {
+        let (start, step, len) = line_layout(self.rows, self.cols, direction, index);
+        Line {
+            data: &self.data,
+            start,
+            step,
+            len,
+        }
+    }
+
+    pub fn line_mut(&mut self, direction: Direction, index: usize) -> LineMut<'_, T> {
+        let (start, step, len) = line_layout(self.rows, self.cols, direction, index);
+        LineMut {
+            data: Cell::from_mut(&mut self.data[..]).as_slice_of_cells(),
+            start,
+            step,
+            len,
+        }
+    }
+
+    pub fn lines(&self, direction: Direction) -> Lines<'_, T> {
+        Lines {
+            matrix: self,
+            direction,
+            index: 0,
+        }
+    }
+
+    pub fn lines_mut(&mut self, direction: Direction) -> LinesMut<'_, T> {
+        LinesMut {
+            data: Cell::from_mut(&mut self.data[..]).as_slice_of_cells(),
+            rows: self.rows,
+            cols: self.cols,
+            direction,
+            index: 0,
+        }
+    }
+
     fn translate(&self, row: usize, col: usize) -> usize {
         row * self.cols + col
     }
 }
 
+impl<T: Clone> Matrix<T> {
+    pub fn filled(rows: usize, cols: usize, value: T) -> Matrix<T> {
+        Matrix {
+            data: vec![value; rows * cols],
+            rows,
+            cols,
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<Vec<T>> {
+        self.row_iter().map(|row| row.to_vec()).collect()
+    }
+}
+
 impl<T> Index<usize> for Matrix<T> {
     type Output = [T];
 
@@ -41,6 +169,191 @@ impl<T> IndexMut<usize> for Matrix<T> {
     }
 }
 
+impl<T: fmt::Debug> fmt::Debug for Matrix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.row_iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq<[Vec<T>]> for Matrix<T> {
+    fn eq(&self, other: &[Vec<T>]) -> bool {
+        self.rows == other.len()
+            && self
+                .row_iter()
+                .zip(other)
+                .all(|(row, other)| row == &other[..])
+    }
+}
+
+impl<T: PartialEq> PartialEq<Vec<Vec<T>>> for Matrix<T> {
+    fn eq(&self, other: &Vec<Vec<T>>) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<'a, T> Line<'a, T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a T> {
+        if index < self.len {
+            Some(&self.data[position(self.start, self.step, index)])
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> + 'a {
+        let (data, start, step) = (self.data, self.start, self.step);
+        (0..self.len).map(move |index| &data[position(start, step, index)])
+    }
+
+    /// The part of the line from index `from` up to, but excluding, `to`.
+    pub fn segment(&self, from: usize, to: usize) -> Line<'a, T> {
+        assert!(from <= to && to <= self.len, "Line segment out of range");
+        Line {
+            data: self.data,
+            start: position(self.start, self.step, from),
+            step: self.step,
+            len: to - from,
+        }
+    }
+}
+
+impl<T> Index<usize> for Line<'_, T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("Line index out of range")
+    }
+}
+
+impl<T> LineMut<'_, T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn set(&mut self, index: usize, value: T) {
+        self.cell(index).set(value);
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.cell(a).swap(self.cell(b));
+    }
+
+    /// The part of the line from index `from` up to, but excluding, `to`.
+    pub fn segment_mut(&mut self, from: usize, to: usize) -> LineMut<'_, T> {
+        assert!(from <= to && to <= self.len, "Line segment out of range");
+        LineMut {
+            data: self.data,
+            start: position(self.start, self.step, from),
+            step: self.step,
+            len: to - from,
+        }
+    }
+
+    fn cell(&self, index: usize) -> &Cell<T> {
+        assert!(index < self.len, "Line index out of range");
+        &self.data[position(self.start, self.step, index)]
+    }
+}
+
+impl<T: Copy> LineMut<'_, T> {
+    pub fn get(&self, index: usize) -> T {
+        self.cell(index).get()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).map(move |index| self.get(index))
+    }
+}
+
+/// A whole slice as one line, for shifting values that are not stored in a
+/// matrix.
+impl<'a, T> From<&'a mut [T]> for LineMut<'a, T> {
+    fn from(values: &'a mut [T]) -> LineMut<'a, T> {
+        LineMut {
+            len: values.len(),
+            data: Cell::from_mut(values).as_slice_of_cells(),
+            start: 0,
+            step: 1,
+        }
+    }
+}
+
+impl<'a, T> Iterator for Lines<'a, T> {
+    type Item = Line<'a, T>;
+
+    fn next(&mut self) -> Option<Line<'a, T>> {
+        if self.index == self.matrix.line_count(self.direction) {
+            return None;
+        }
+        self.index += 1;
+        Some(self.matrix.line(self.direction, self.index - 1))
+    }
+}
+
+impl<'a, T> Iterator for LinesMut<'a, T> {
+    type Item = LineMut<'a, T>;
+
+    fn next(&mut self) -> Option<LineMut<'a, T>> {
+        if self.index == line_count(self.rows, self.cols, self.direction) {
+            return None;
+        }
+        let (start, step, len) = line_layout(self.rows, self.cols, self.direction, self.index);
+        self.index += 1;
+        Some(LineMut {
+            data: self.data,
+            start,
+            step,
+            len,
+        })
+    }
+}
+
+fn line_count(rows: usize, cols: usize, direction: Direction) -> usize {
+    match direction {
+        Direction::Left | Direction::Right => rows,
+        Direction::Up | Direction::Down => cols,
+    }
+}
+
+/// Where line `index` starts in the data, the step between its cells and its
+/// length.
+fn line_layout(
+    rows: usize,
+    cols: usize,
+    direction: Direction,
+    index: usize,
+) -> (usize, isize, usize) {
+    assert!(
+        index < line_count(rows, cols, direction),
+        "Line {} is out of range for {:?}",
+        index,
+        direction
+    );
+    let step = cols as isize;
+    match direction {
+        Direction::Left => (index * cols, 1, cols),
+        Direction::Right => (index * cols + cols - 1, -1, cols),
+        Direction::Up => (index, step, rows),
+        Direction::Down => ((rows - 1) * cols + index, -step, rows),
+    }
+}
+
+fn position(start: usize, step: isize, index: usize) -> usize {
+    (start as isize + step * index as isize) as usize
+}
+
 #[test]
 fn test_new() {
     let m = Matrix::new(vec![vec![1, 0, 0], vec![0, 1, 0], vec![0, 0, 1]]);
@@ -107,3 +420,157 @@ fn test_index_mut() {
     assert_eq!(m[3][1], 60);
     assert_eq!(m[3][2], 40);
 }
+
+#[cfg(test)]
+fn sample() -> Matrix<i32> {
+    Matrix::new(vec![vec![1, 2, 3], vec![4, 5, 6]])
+}
+
+#[test]
+fn test_filled_and_to_vec() {
+    let m = Matrix::filled(2, 3, 0);
+    assert_eq!(m.to_vec(), vec![vec![0, 0, 0], vec![0, 0, 0]]);
+    assert_eq!(sample().to_vec(), vec![vec![1, 2, 3], vec![4, 5, 6]]);
+    assert_eq!(sample(), vec![vec![1, 2, 3], vec![4, 5, 6]]);
+    assert_eq!(format!("{:?}", sample()), "[[1, 2, 3], [4, 5, 6]]");
+}
+
+#[test]
+fn test_rows_and_columns() {
+    let mut m = sample();
+    assert_eq!(
+        m.row_iter().collect::<Vec<&[i32]>>(),
+        vec![&[1, 2, 3], &[4, 5, 6]]
+    );
+    assert_eq!(
+        m.column(1).iter().copied().collect::<Vec<i32>>(),
+        vec![2, 5]
+    );
+    assert_eq!(
+        m.column_iter()
+            .map(|column| column.iter().sum())
+            .collect::<Vec<i32>>(),
+        vec![5, 7, 9]
+    );
+
+    for row in m.row_iter_mut() {
+        row[0] *= 10;
+    }
+    m.column_mut(2).set(1, 60);
+    for mut column in m.column_iter_mut() {
+        column.set(0, column.get(0) + 1);
+    }
+    assert_eq!(m, vec![vec![11, 3, 4], vec![40, 5, 60]]);
+}
+
+#[test]
+fn test_lines_follow_move_order() {
+    let m = sample();
+    let lines = |direction| {
+        m.lines(direction)
+            .map(|line| line.iter().copied().collect())
+            .collect::<Vec<Vec<i32>>>()
+    };
+    assert_eq!(lines(Direction::Left), vec![vec![1, 2, 3], vec![4, 5, 6]]);
+    assert_eq!(lines(Direction::Right), vec![vec![3, 2, 1], vec![6, 5, 4]]);
+    assert_eq!(
+        lines(Direction::Up),
+        vec![vec![1, 4], vec![2, 5], vec![3, 6]]
+    );
+    assert_eq!(
+        lines(Direction::Down),
+        vec![vec![4, 1], vec![5, 2], vec![6, 3]]
+    );
+    assert_eq!(m.line(Direction::Down, 2).get(2), None);
+}
+
+#[test]
+fn test_lines_mut() {
+    let mut m = sample();
+    for mut line in m.lines_mut(Direction::Down) {
+        line.swap(0, 1);
+    }
+    assert_eq!(m, vec![vec![4, 5, 6], vec![1, 2, 3]]);
+
+    let mut line = m.line_mut(Direction::Right, 0);
+    line.set(0, 0);
+    assert_eq!(line.iter().collect::<Vec<i32>>(), vec![0, 5, 4]);
+    assert_eq!(m[0], [4, 5, 0]);
+
+    let mut lines: Vec<LineMut<i32>> = m.lines_mut(Direction::Up).collect();
+    lines[0].set(0, 7);
+    lines[2].set(1, 9);
+    lines.swap(0, 2);
+    assert_eq!(lines[2].get(0), 7);
+    assert_eq!(m, vec![vec![7, 5, 0], vec![1, 2, 9]]);
+
+    let mut values = [1, 2, 3];
+    let mut line = LineMut::from(&mut values[..]);
+    line.swap(0, 2);
+    line.segment_mut(1, 3).set(0, 8);
+    assert_eq!(values, [3, 8, 1]);
+}
+
+/// Column views replace the baseline `transpose`: the columns of a matrix in
+/// `Up` order are the rows of its transpose.
+#[cfg(test)]
+fn transpose(rows: Vec<Vec<i32>>) -> Vec<Vec<i32>> {
+    Matrix::new(rows)
+        .column_iter()
+        .map(|column| column.iter().copied().collect())
+        .collect()
+}
+
+#[test]
+fn test_transpose_square_matrix() {
+    let v = vec![
+        vec![1, 2, 3, 4],
+        vec![5, 6, 7, 8],
+        vec![9, 10, 11, 12],
+        vec![13, 14, 15, 16],
+    ];
+    assert_eq!(
+        transpose(v),
+        vec![
+            vec![1, 5, 9, 13],
+            vec![2, 6, 10, 14],
+            vec![3, 7, 11, 15],
+            vec![4, 8, 12, 16]
+        ]
+    );
+}
+
+#[test]
+fn test_transpose_identity_matrix() {
+    let v = vec![
+        vec![1, 0, 0, 0],
+        vec![0, 1, 0, 0],
+        vec![0, 0, 1, 0],
+        vec![0, 0, 0, 1],
+    ];
+    assert_eq!(transpose(v.clone()), v);
+}
+
+#[test]
+fn test_transpose_rectangular_matrix() {
+    let v = vec![vec![1, 2, 3], vec![4, 5, 6]];
+    assert_eq!(
+        transpose(v.clone()),
+        vec![vec![1, 4], vec![2, 5], vec![3, 6]]
+    );
+    assert_eq!(transpose(transpose(v.clone())), v);
+}
+
+#[test]
+fn test_line_segments() {
+    let mut m = sample();
+    let line = m.line(Direction::Right, 1);
+    let segment = line.segment(1, 3);
+    assert_eq!(segment.iter().copied().collect::<Vec<i32>>(), vec![5, 4]);
+    assert!(line.segment(3, 3).is_empty());
+
+    let mut line = m.line_mut(Direction::Right, 1);
+    let mut segment = line.segment_mut(1, 3);
+    segment.swap(0, 1);
+    assert_eq!(m[1], [5, 4, 6]);
+}
@@ -0,0 +1,346 @@
+/// Decides which neighbouring tiles merge when a line is shifted. `first` is
+/// the tile nearer to the edge the tiles move towards.
+pub trait MergeRule {
+    /// How many neighbouring tiles merge into one. Every neighbouring pair of
+    /// a group must pass `can_merge`; larger groups fold `merge` over the
+    /// group and score the last step.
+    fn group_size(&self) -> usize {
+        2
+    }
+
+    fn can_merge(&self, first: i32, second: i32) -> bool;
+
+    fn merge(&self, first: i32, second: i32) -> i32;
+
+    fn score(&self, first: i32, second: i32) -> i32 {
+        self.merge(first, second)
+    }
+
+    /// Whether `cell` can appear on a board whose spawned tiles are `tile`.
+    /// Used to validate loaded boards; rules that cannot tell accept anything.
+    fn can_build(&self, cell: i32, tile: i32) -> bool {
+        let _ = (cell, tile);
+        true
+    }
+
+    /// The milestone recorded after `tile` has been reached.
+    fn next_milestone(&self, tile: i32) -> i32 {
+        tile.saturating_mul(2)
+    }
+
+    /// Whether the rule merges exactly like `ClassicMerge`, so plain boards
+    /// can use the classic fast paths in `board`.
+    fn is_classic(&self) -> bool {
+        false
+    }
+}
+
+/// Equal tiles merge into their sum, which is also the score.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClassicMerge;
+
+impl MergeRule for ClassicMerge {
+    fn can_merge(&self, first: i32, second: i32) -> bool {
+        first == second
+    }
+
+    fn merge(&self, first: i32, second: i32) -> i32 {
+        first + second
+    }
+
+    fn can_build(&self, mut cell: i32, tile: i32) -> bool {
+        if tile <= 0 {
+            return false;
+        }
+        while cell > tile && cell % 2 == 0 {
+            cell /= 2;
+        }
+        cell == tile
+    }
+
+    fn is_classic(&self) -> bool {
+        true
+    }
+}
+
+/// Neighbouring Fibonacci numbers merge into the next one: 1+1, 1+2, 2+3 and
+/// so on. The merged value is the score.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FibonacciMerge;
+
+impl MergeRule for FibonacciMerge {
+    fn can_merge(&self, first: i32, second: i32) -> bool {
+        let (low, high) = (first.min(second), first.max(second));
+        if low == 1 && high == 1 {
+            return true;
+        }
+        low > 0 && is_fibonacci(low) && next_fibonacci(low) == high
+    }
+
+    fn merge(&self, first: i32, second: i32) -> i32 {
+        first + second
+    }
+
+    fn can_build(&self, cell: i32, tile: i32) -> bool {
+        is_fibonacci(cell) && (cell == tile || tile == 1)
+    }
+
+    fn next_milestone(&self, tile: i32) -> i32 {
+        next_fibonacci(tile)
+    }
+}
+
+/// The Threes! rule: 1 and 2 merge into 3, and equal tiles of 3 or more
+/// merge into their sum. The merged value is the score.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ThreesMerge;
+
+impl MergeRule for ThreesMerge {
+    fn can_merge(&self, first: i32, second: i32) -> bool {
+        match (first, second) {
+            (1, 2) | (2, 1) => true,
+            _ => first >= 3 && first == second,
+        }
+    }
+
+    fn merge(&self, first: i32, second: i32) -> i32 {
+        first + second
+    }
+
+    fn can_build(&self, mut cell: i32, tile: i32) -> bool {
+        if cell == tile {
+            return true;
+        }
+        if tile != 1 && tile != 2 && tile != 3 {
+            return false;
+        }
+        while cell > 3 && cell % 2 == 0 {
+            cell /= 2;
+        }
+        cell == 3
+    }
+}
+
+/// `base` equal tiles in a row merge into one tile of `base` times the value,
+/// for example 3, 3, 3 into 9. The merged value is the score.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BaseMerge {
+    base: i32,
+}
+
+impl BaseMerge {
+    pub fn new(base: i32) -> Result<BaseMerge, String> {
+        if base < 2 {
+            return Err(format!("Base should be at least 2. Actual base: {}", base));
+        }
+        Ok(BaseMerge { base })
+    }
+
+    pub fn base(&self) -> i32 {
+        self.base
+    }
+}
+
+impl MergeRule for BaseMerge {
+    fn group_size(&self) -> usize {
+        self.base as usize
+    }
+
+    fn can_merge(&self, first: i32, second: i32) -> bool {
+        first == second
+    }
+
+    fn merge(&self, first: i32, second: i32) -> i32 {
+        first + second
+    }
+
+    fn can_build(&self, mut cell: i32, tile: i32) -> bool {
+        if tile <= 0 {
+            return false;
+        }
+        while cell > tile && cell % self.base == 0 {
+            cell /= self.base;
+        }
+        cell == tile
+    }
+
+    fn next_milestone(&self, tile: i32) -> i32 {
+        tile.saturating_mul(self.base)
+    }
+}
+
+/// One of the built-in rules, chosen at runtime, for example when a replay
+/// is loaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnyMerge {
+    #[default]
+    Classic,
+    Fibonacci,
+    Threes,
+    Base(BaseMerge),
+}
+
+impl AnyMerge {
+    fn rule(&self) -> &dyn MergeRule {
+        match self {
+            AnyMerge::Classic => &ClassicMerge,
+            AnyMerge::Fibonacci => &FibonacciMerge,
+            AnyMerge::Threes => &ThreesMerge,
+            AnyMerge::Base(rule) => rule,
+        }
+    }
+}
+
+impl MergeRule for AnyMerge {
+    fn group_size(&self) -> usize {
+        self.rule().group_size()
+    }
+
+    fn can_merge(&self, first: i32, second: i32) -> bool {
+        self.rule().can_merge(first, second)
+    }
+
+    fn merge(&self, first: i32, second: i32) -> i32 {
+        self.rule().merge(first, second)
+    }
+
+    fn score(&self, first: i32, second: i32) -> i32 {
+        self.rule().score(first, second)
+    }
+
+    fn can_build(&self, cell: i32, tile: i32) -> bool {
+        self.rule().can_build(cell, tile)
+    }
+
+    fn next_milestone(&self, tile: i32) -> i32 {
+        self.rule().next_milestone(tile)
+    }
+
+    fn is_classic(&self) -> bool {
+        self.rule().is_classic()
+    }
+}
+
+impl From<ClassicMerge> for AnyMerge {
+    fn from(_: ClassicMerge) -> AnyMerge {
+        AnyMerge::Classic
+    }
+}
+
+impl From<FibonacciMerge> for AnyMerge {
+    fn from(_: FibonacciMerge) -> AnyMerge {
+        AnyMerge::Fibonacci
+    }
+}
+
+impl From<ThreesMerge> for AnyMerge {
+    fn from(_: ThreesMerge) -> AnyMerge {
+        AnyMerge::Threes
+    }
+}
+
+impl From<BaseMerge> for AnyMerge {
+    fn from(rule: BaseMerge) -> AnyMerge {
+        AnyMerge::Base(rule)
+    }
+}
+
+fn is_fibonacci(value: i32) -> bool {
+    value == 1 || value > 0 && next_fibonacci(value - 1) == value
+}
+
+/// The smallest Fibonacci number greater than `value`.
+fn next_fibonacci(value: i32) -> i32 {
+    let (mut a, mut b) = (1i32, 2i32);
+    while a <= value && a < i32::MAX {
+        let next = a.saturating_add(b);
+        a = b;
+        b = next;
+    }
+    a
+}
+
+#[test]
+fn test_classic_merge() {
+    let rule = ClassicMerge;
+    assert!(rule.can_merge(4, 4));
+    assert!(!rule.can_merge(4, 8));
+    assert_eq!(rule.merge(4, 4), 8);
+    assert_eq!(rule.score(4, 4), 8);
+    assert!(rule.can_build(2, 2));
+    assert!(rule.can_build(64, 4));
+    assert!(!rule.can_build(6, 2));
+    assert!(!rule.can_build(2, 4));
+    assert!(!rule.can_build(8, 0));
+    assert!(rule.is_classic());
+    assert!(!FibonacciMerge.is_classic());
+}
+
+#[test]
+fn test_fibonacci_merge() {
+    let rule = FibonacciMerge;
+    assert!(rule.can_merge(1, 1));
+    assert!(rule.can_merge(1, 2));
+    assert!(rule.can_merge(3, 2));
+    assert!(rule.can_merge(34, 55));
+    assert!(!rule.can_merge(2, 2));
+    assert!(!rule.can_merge(1, 3));
+    assert!(!rule.can_merge(4, 7));
+    assert!(!rule.can_merge(0, 1));
+    assert_eq!(rule.merge(2, 3), 5);
+    assert_eq!(rule.score(1, 1), 2);
+    assert!(rule.can_build(89, 1));
+    assert!(rule.can_build(2, 2));
+    assert!(!rule.can_build(5, 2));
+    assert!(!rule.can_build(4, 1));
+    assert_eq!(rule.next_milestone(2584), 4181);
+    assert_eq!(rule.next_milestone(1), 2);
+    assert_eq!(ClassicMerge.next_milestone(2048), 4096);
+}
+
+#[test]
+fn test_threes_merge() {
+    let rule = ThreesMerge;
+    assert!(rule.can_merge(1, 2));
+    assert!(rule.can_merge(2, 1));
+    assert!(rule.can_merge(6, 6));
+    assert!(!rule.can_merge(1, 1));
+    assert!(!rule.can_merge(2, 2));
+    assert!(!rule.can_merge(3, 6));
+    assert_eq!(rule.merge(1, 2), 3);
+    assert_eq!(rule.score(12, 12), 24);
+    assert!(rule.can_build(2, 2));
+    assert!(rule.can_build(384, 1));
+    assert!(!rule.can_build(2, 1));
+    assert!(!rule.can_build(9, 3));
+    assert_eq!(rule.next_milestone(384), 768);
+}
+
+#[test]
+fn test_base_merge() {
+    assert!(BaseMerge::new(1).is_err());
+    let rule = BaseMerge::new(3).unwrap();
+    assert_eq!(rule.base(), 3);
+    assert_eq!(rule.group_size(), 3);
+    assert!(rule.can_merge(3, 3));
+    assert!(!rule.can_merge(3, 9));
+    assert_eq!(rule.merge(rule.merge(3, 3), 3), 9);
+    assert_eq!(rule.score(6, 3), 9);
+    assert!(rule.can_build(81, 3));
+    assert!(rule.can_build(27, 9));
+    assert!(!rule.can_build(18, 3));
+    assert_eq!(rule.next_milestone(2187), 6561);
+    assert_eq!(ClassicMerge.group_size(), 2);
+}
+
+#[test]
+fn test_any_merge() {
+    let base = BaseMerge::new(3).unwrap();
+    assert_eq!(AnyMerge::from(ClassicMerge), AnyMerge::default());
+    assert!(AnyMerge::Classic.is_classic());
+    assert!(AnyMerge::Fibonacci.can_merge(2, 3));
+    assert!(AnyMerge::Threes.can_merge(1, 2));
+    assert!(!AnyMerge::Threes.can_merge(1, 1));
+    assert_eq!(AnyMerge::from(base).group_size(), 3);
+    assert_eq!(AnyMerge::from(base).next_milestone(9), 27);
+}
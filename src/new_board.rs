@@ -1,153 +1,905 @@
-use crate::random::RandomNumberGenerator;
+use crate::board::{self, Direction, Movement, WALL};
+use crate::matrix::{Line, LineMut, Matrix};
+use crate::merge_rule::{ClassicMerge, MergeRule};
+use crate::random::{RandomNumberGenerator, RngState, SnapshotGenerator};
 use crate::tile_generator::TileGenerator;
 
-struct Board<R: RandomNumberGenerator> {
-    cells: Vec<Vec<i32>>,
+#[derive(Clone)]
+pub(crate) struct Board<R: RandomNumberGenerator, M: MergeRule = ClassicMerge> {
+    cells: Matrix<i32>,
+    mask: Matrix<bool>,
     generator: TileGenerator<R>,
+    rule: M,
 }
 
-impl<R: RandomNumberGenerator> Board<R> {}
+impl<R: RandomNumberGenerator, M: MergeRule> Board<R, M> {
+    pub fn from_cells(
+        cells: Vec<Vec<i32>>,
+        mask: Matrix<bool>,
+        generator: TileGenerator<R>,
+        rule: M,
+    ) -> Board<R, M> {
+        Board {
+            cells: Matrix::new(cells),
+            mask,
+            generator,
+            rule,
+        }
+    }
+
+    pub fn rule(&self) -> &M {
+        &self.rule
+    }
+
+    pub fn cells(&self) -> &Matrix<i32> {
+        &self.cells
+    }
+
+    pub fn mask(&self) -> &Matrix<bool> {
+        &self.mask
+    }
+
+    pub fn next_tile(&self) -> Option<i32> {
+        self.generator.peek_tile()
+    }
+
+    pub fn bag(&self) -> Option<&[i32]> {
+        self.generator.bag()
+    }
+
+    /// Returns the score and, for every part of a line between walls that
+    /// changed, the cell at its far end from `direction`.
+    pub fn shift(
+        &mut self,
+        direction: Direction,
+        movement: Movement,
+    ) -> (i32, Vec<(usize, usize)>) {
+        shift_cells(&mut self.cells, &self.mask, direction, movement, &self.rule)
+    }
 
-fn transpose(cells: &mut Vec<Vec<i32>>) {
-    for i in 0..cells.len() {
-        for j in 0..i {
-            let temp = cells[i][j];
-            cells[i][j] = cells[j][i];
-            cells[j][i] = temp;
+    pub fn legal_moves(&self, movement: Movement) -> Vec<Direction> {
+        if movement == Movement::Slide && self.rule.is_classic() {
+            if let Some(cells) = self.plain_4x4() {
+                return board::legal_moves_4x4(&cells);
+            }
         }
+        board::DIRECTIONS
+            .iter()
+            .copied()
+            .filter(|direction| {
+                can_shift(&self.cells, &self.mask, *direction, movement, &self.rule)
+            })
+            .collect()
+    }
+
+    /// The cells of a 4x4 board with no masked out cells.
+    fn plain_4x4(&self) -> Option<[[i32; 4]; 4]> {
+        if self.cells.rows() != 4 || self.cells.cols() != 4 {
+            return None;
+        }
+        if self.mask.row_iter().flatten().any(|active| !active) {
+            return None;
+        }
+        let mut cells = [[0; 4]; 4];
+        for (i, row) in self.cells.row_iter().enumerate() {
+            cells[i].copy_from_slice(row);
+        }
+        Some(cells)
+    }
+
+    pub fn add_random_tile(&mut self) -> Option<(usize, usize, i32)> {
+        let empty_cells = select_empty_cells(&self.cells, &self.mask);
+        if empty_cells.is_empty() {
+            return None;
+        }
+        let (i, j, tile) = self.generator.generate_tile(&empty_cells);
+        self.cells[i][j] = tile;
+        Some((i, j, tile))
+    }
+
+    /// Adds a tile on one of the empty `edges` returned by `shift`.
+    pub fn add_edge_tile(&mut self, edges: &[(usize, usize)]) -> Option<(usize, usize, i32)> {
+        let empty_cells: Vec<(usize, usize)> = edges
+            .iter()
+            .copied()
+            .filter(|(i, j)| self.cells[*i][*j] == 0)
+            .collect();
+        if empty_cells.is_empty() {
+            return None;
+        }
+        let (i, j, tile) = self.generator.generate_tile(&empty_cells);
+        self.cells[i][j] = tile;
+        Some((i, j, tile))
     }
 }
 
-fn compactify_row_left(v: &mut [i32]) {
-    let first_empty_opt = find_first_empty(v);
-    let mut first_empty = match first_empty_opt {
-        Some(index) => index,
-        None => return,
-    };
-    let mut current = first_empty + 1;
-    while current < v.len() {
-        while current < v.len() && v[current] == 0 {
-            current += 1;
+impl<R: SnapshotGenerator, M: MergeRule> Board<R, M> {
+    pub fn rng_state(&self) -> RngState {
+        self.generator.rng_state()
+    }
+
+    pub fn restore_rng_state(&mut self, state: &RngState) -> Result<(), String> {
+        self.generator.restore_rng_state(state)
+    }
+}
+
+fn shift_cells<M: MergeRule>(
+    cells: &mut Matrix<i32>,
+    mask: &Matrix<bool>,
+    direction: Direction,
+    movement: Movement,
+    rule: &M,
+) -> (i32, Vec<(usize, usize)>) {
+    let mut score = 0;
+    let mut edges = vec![];
+    // The values of the active cells of the current line.
+    let mut scratch = Vec::with_capacity(cells.line_count(direction));
+    let lines = cells.lines_mut(direction).zip(mask.lines(direction));
+    for (index, (mut line, active)) in lines.enumerate() {
+        scratch.clear();
+        scratch.extend((0..line.len()).filter(|k| active[*k]).map(|k| line.get(k)));
+        // Full lines shift in place and keep their old values in `scratch`.
+        // Tiles skip masked out cells, so other lines shift `scratch` and
+        // write it back.
+        let full = scratch.len() == line.len();
+        score += if full {
+            shift_segments(&mut line, movement, rule)
+        } else {
+            shift_values(&mut scratch, movement, rule)
+        };
+        let mut changed = false;
+        let mut edge = None;
+        let positions = (0..line.len()).filter(|k| active[*k]);
+        for (k, value) in positions.zip(scratch.iter().copied()) {
+            let (before, after) = if full {
+                (value, line.get(k))
+            } else {
+                let before = line.get(k);
+                line.set(k, value);
+                (before, value)
+            };
+            if after == WALL {
+                edges.extend(edge.filter(|_| changed));
+                changed = false;
+                edge = None;
+            } else {
+                changed |= before != after;
+                edge = Some(line_cell(mask, direction, index, k));
+            }
         }
-        if current == v.len() {
-            return;
+        edges.extend(edge.filter(|_| changed));
+    }
+    (score, edges)
+}
+
+/// Shifts one line of values, given in move order, towards index 0 in place.
+/// Returns the score.
+pub(crate) fn shift_values<M: MergeRule>(values: &mut [i32], movement: Movement, rule: &M) -> i32 {
+    shift_segments(&mut LineMut::from(values), movement, rule)
+}
+
+/// Shifts each part of `line` between walls towards index 0. Returns the
+/// score.
+fn shift_segments<M: MergeRule>(line: &mut LineMut<i32>, movement: Movement, rule: &M) -> i32 {
+    let mut score = 0;
+    let mut from = 0;
+    for to in 0..=line.len() {
+        if to < line.len() && line.get(to) != WALL {
+            continue;
+        }
+        if to > from {
+            let whole = to - from == line.len();
+            let mut segment = line.segment_mut(from, to);
+            score += match movement {
+                Movement::Slide => shift_line(&mut segment, rule),
+                Movement::Step => step_line(&mut segment, rule),
+                Movement::Wrap if whole => wrap_line(&mut segment, rule),
+                Movement::Wrap => shift_line(&mut segment, rule),
+            };
         }
-        v.swap(first_empty, current);
-        first_empty += 1;
-        current += 1;
+        from = to + 1;
     }
+    score
 }
 
-fn compactify_row_right(v: &mut [i32]) {
-    let first_empty_opt = find_last_empty(v);
-    let mut first_empty = match first_empty_opt {
-        Some(index) => index,
-        None => return,
-    };
-    let mut current: i32 = first_empty as i32 - 1;
-    while current > -1 {
-        while current > -1 && v[current as usize] == 0 {
-            current -= 1;
+/// Whether `shift_values` would change `values`.
+pub(crate) fn can_shift_values<M: MergeRule>(values: &[i32], movement: Movement, rule: &M) -> bool {
+    let size = rule.group_size();
+    let wraps = movement == Movement::Wrap && !values.contains(&WALL);
+    if wraps {
+        let tiles: Vec<i32> = values.iter().copied().filter(|tile| *tile != 0).collect();
+        if wrap_rotation(|k| tiles[k], tiles.len(), rule).is_some() {
+            return true;
+        }
+    }
+    segments(values.iter()).into_iter().any(|(from, to)| {
+        let line = &values[from..to];
+        (1..line.len()).any(|k| line[k] != 0 && line[k - 1] == 0)
+            || (0..(line.len() + 1).saturating_sub(size))
+                .any(|start| can_merge_group(|k| line[k], start, size, rule))
+    })
+}
+
+/// Indices of the active cells of a mask line. Tiles move along these cells
+/// only, skipping masked out ones.
+fn active_positions(line: Line<bool>) -> Vec<usize> {
+    (0..line.len()).filter(|k| line[*k]).collect()
+}
+
+/// The row and column of cell `k` of line `index` when moving in
+/// `direction`.
+fn line_cell<T>(
+    matrix: &Matrix<T>,
+    direction: Direction,
+    index: usize,
+    k: usize,
+) -> (usize, usize) {
+    let (rows, cols) = (matrix.rows(), matrix.cols());
+    match direction {
+        Direction::Left => (index, k),
+        Direction::Right => (index, cols - 1 - k),
+        Direction::Up => (k, index),
+        Direction::Down => (rows - 1 - k, index),
+    }
+}
+
+fn can_shift<M: MergeRule>(
+    cells: &Matrix<i32>,
+    mask: &Matrix<bool>,
+    direction: Direction,
+    movement: Movement,
+    rule: &M,
+) -> bool {
+    cells
+        .lines(direction)
+        .zip(mask.lines(direction))
+        .any(|(line, active)| {
+            let values: Vec<i32> = active_positions(active)
+                .into_iter()
+                .map(|k| line[k])
+                .collect();
+            can_shift_values(&values, movement, rule)
+        })
+}
+
+/// The index ranges of `line` between walls, each shifted on its own.
+fn segments<'a>(line: impl Iterator<Item = &'a i32>) -> Vec<(usize, usize)> {
+    let mut segments = vec![];
+    let mut from = 0;
+    let mut len = 0;
+    for (k, cell) in line.enumerate() {
+        if *cell == WALL {
+            if k > from {
+                segments.push((from, k));
+            }
+            from = k + 1;
+        }
+        len = k + 1;
+    }
+    if len > from {
+        segments.push((from, len));
+    }
+    segments
+}
+
+fn select_empty_cells(cells: &Matrix<i32>, mask: &Matrix<bool>) -> Vec<(usize, usize)> {
+    let mut empty_cells = vec![];
+    for (i, (row, active)) in cells.row_iter().zip(mask.row_iter()).enumerate() {
+        for (j, cell) in row.iter().enumerate() {
+            if *cell == 0 && active[j] {
+                empty_cells.push((i, j));
+            }
         }
-        if current == -1 {
-            return;
+    }
+    empty_cells
+}
+
+/// Moves the tiles of `line` towards index 0, merging each tile at most once.
+fn shift_line<M: MergeRule>(line: &mut LineMut<i32>, rule: &M) -> i32 {
+    compactify_line(line);
+    let size = rule.group_size();
+    let mut score = 0;
+    let mut i = 0;
+    while i + size <= line.len() && line.get(i) != 0 {
+        if can_merge_group(|k| line.get(k), i, size, rule) {
+            score += merge_group(line, i, size, rule);
+            i += size;
+        } else {
+            i += 1;
         }
-        v.swap(first_empty, current as usize);
-        first_empty -= 1;
-        current -= 1;
     }
+    compactify_line(line);
+    score
+}
+
+/// Shifts a line whose ends touch. If the tiles nearest the far end can merge
+/// with the tiles nearest index 0, that group merges first and lands at
+/// index 0. The remaining tiles then pair up in order, as in `shift_line`.
+fn wrap_line<M: MergeRule>(line: &mut LineMut<i32>, rule: &M) -> i32 {
+    compactify_line(line);
+    let count = line.iter().take_while(|tile| *tile != 0).count();
+    if let Some(rotation) = wrap_rotation(|k| line.get(k), count, rule) {
+        // Rotating the tiles right is reversing them, then each part.
+        reverse_line(&mut line.segment_mut(0, count));
+        reverse_line(&mut line.segment_mut(0, rotation));
+        reverse_line(&mut line.segment_mut(rotation, count));
+    }
+    shift_line(line, rule)
 }
 
-fn find_first_empty(v: &[i32]) -> Option<usize> {
-    return v.iter().position(|&x| x == 0);
+/// How many of the last of `count` tiles join the first ones to form a group
+/// that merges across the wrap, if any. `tile(k)` is the `k`th tile of the
+/// line, ignoring empty cells.
+fn wrap_rotation<F, M>(tile: F, count: usize, rule: &M) -> Option<usize>
+where
+    F: Fn(usize) -> i32,
+    M: MergeRule,
+{
+    let size = rule.group_size();
+    if count < size {
+        return None;
+    }
+    (1..size).find(|rotation| {
+        let group = |k: usize| {
+            if k < *rotation {
+                tile(count - rotation + k)
+            } else {
+                tile(k - rotation)
+            }
+        };
+        can_merge_group(group, 0, size, rule)
+    })
 }
 
-fn find_last_empty(v: &[i32]) -> Option<usize> {
-    return v.iter().rposition(|&x| x == 0);
+/// Moves the tiles of `line` at most one cell towards index 0. The first tile
+/// that can move or merge does so, and every tile behind it follows.
+fn step_line<M: MergeRule>(line: &mut LineMut<i32>, rule: &M) -> i32 {
+    let size = rule.group_size();
+    for k in 1..line.len() {
+        if line.get(k) != 0 && line.get(k - 1) == 0 {
+            close_gap(line, k, 1);
+            return 0;
+        }
+        if k + 1 >= size && can_merge_group(|k| line.get(k), k + 1 - size, size, rule) {
+            let score = merge_group(line, k + 1 - size, size, rule);
+            close_gap(line, k + 1, size - 1);
+            return score;
+        }
+    }
+    0
+}
+
+/// Whether the `size` tiles starting at `start` merge into one. `tile(k)` is
+/// the value of cell `k`.
+fn can_merge_group<F, M>(tile: F, start: usize, size: usize, rule: &M) -> bool
+where
+    F: Fn(usize) -> i32,
+    M: MergeRule,
+{
+    tile(start) != 0
+        && (start + 1..start + size).all(|k| tile(k) != 0 && rule.can_merge(tile(k - 1), tile(k)))
+}
+
+/// Merges the group starting at `start` into its first cell.
+fn merge_group<M: MergeRule>(line: &mut LineMut<i32>, start: usize, size: usize, rule: &M) -> i32 {
+    let mut score = 0;
+    for k in start + 1..start + size {
+        score = rule.score(line.get(start), line.get(k));
+        line.set(start, rule.merge(line.get(start), line.get(k)));
+        line.set(k, 0);
+    }
+    score
+}
+
+/// Moves the tiles from index `from` onwards `by` cells towards index 0.
+fn close_gap(line: &mut LineMut<i32>, from: usize, by: usize) {
+    for m in from..line.len() {
+        line.swap(m - by, m);
+    }
+}
+
+fn compactify_line(line: &mut LineMut<i32>) {
+    let mut first_empty = 0;
+    for current in 0..line.len() {
+        if line.get(current) != 0 {
+            line.swap(first_empty, current);
+            first_empty += 1;
+        }
+    }
+}
+
+fn reverse_line(line: &mut LineMut<i32>) {
+    for k in 0..line.len() / 2 {
+        line.swap(k, line.len() - 1 - k);
+    }
+}
+
+#[cfg(test)]
+fn unmasked(cells: &Matrix<i32>) -> Matrix<bool> {
+    Matrix::filled(cells.rows(), cells.cols(), true)
+}
+
+#[cfg(test)]
+fn shift_unmasked<M: MergeRule>(
+    cells: &mut Matrix<i32>,
+    direction: Direction,
+    movement: Movement,
+    rule: &M,
+) -> (i32, Vec<(usize, usize)>) {
+    let mask = unmasked(cells);
+    shift_cells(cells, &mask, direction, movement, rule)
+}
+
+#[cfg(test)]
+fn can_shift_unmasked<M: MergeRule>(cells: &Matrix<i32>, direction: Direction, rule: &M) -> bool {
+    can_shift(cells, &unmasked(cells), direction, Movement::Slide, rule)
+}
+
+#[cfg(test)]
+fn apply_to_row(
+    row: &[i32],
+    direction: Direction,
+    f: fn(&mut LineMut<i32>, &ClassicMerge) -> i32,
+) -> Vec<i32> {
+    let mut cells = Matrix::new(vec![row.to_vec()]);
+    f(&mut cells.line_mut(direction, 0), &ClassicMerge);
+    cells.to_vec().remove(0)
+}
+
+/// Compacts towards index 0 of a left or right line view, which replaces the
+/// baseline `compactify_row_left` and `compactify_row_right`.
+#[cfg(test)]
+fn compactify(row: &[i32], direction: Direction) -> Vec<i32> {
+    apply_to_row(row, direction, |line, _| {
+        compactify_line(line);
+        0
+    })
 }
 
 #[test]
 fn test_compactify_row_left() {
-    let mut v1 = vec![2, 0, 0, 0, 2, 0, 4, 0];
-    compactify_row_left(&mut v1);
-    assert_eq!(v1, vec![2, 2, 4, 0, 0, 0, 0, 0]);
+    let v1 = vec![2, 0, 0, 0, 2, 0, 4, 0];
+    assert_eq!(
+        compactify(&v1, Direction::Left),
+        vec![2, 2, 4, 0, 0, 0, 0, 0]
+    );
 
-    let mut v2 = vec![0, 0, 2, 0, 2, 0, 4, 0];
-    compactify_row_left(&mut v2);
-    assert_eq!(v2, vec![2, 2, 4, 0, 0, 0, 0, 0]);
+    let v2 = vec![0, 0, 2, 0, 2, 0, 4, 0];
+    assert_eq!(
+        compactify(&v2, Direction::Left),
+        vec![2, 2, 4, 0, 0, 0, 0, 0]
+    );
 
-    let mut v3 = vec![2, 2, 4, 0, 0, 0, 0, 0];
-    compactify_row_left(&mut v3);
-    assert_eq!(v3, vec![2, 2, 4, 0, 0, 0, 0, 0]);
+    let v3 = vec![2, 2, 4, 0, 0, 0, 0, 0];
+    assert_eq!(
+        compactify(&v3, Direction::Left),
+        vec![2, 2, 4, 0, 0, 0, 0, 0]
+    );
 
-    let mut v4 = vec![2, 2, 4];
-    compactify_row_left(&mut v4);
-    assert_eq!(v4, vec![2, 2, 4]);
+    let v4 = vec![2, 2, 4];
+    assert_eq!(compactify(&v4, Direction::Left), vec![2, 2, 4]);
 
-    let mut v5 = vec![0, 2, 0, 0, 0, 2, 4, 4, 0, 2, 0, 2, 2];
-    compactify_row_left(&mut v5);
-    assert_eq!(v5, vec![2, 2, 4, 4, 2, 2, 2, 0, 0, 0, 0, 0, 0]);
+    let v5 = vec![0, 2, 0, 0, 0, 2, 4, 4, 0, 2, 0, 2, 2];
+    assert_eq!(
+        compactify(&v5, Direction::Left),
+        vec![2, 2, 4, 4, 2, 2, 2, 0, 0, 0, 0, 0, 0]
+    );
 }
 
 #[test]
 fn test_compactify_row_right() {
-    let mut v1 = vec![2, 0, 0, 0, 2, 0, 4, 0];
-    compactify_row_right(&mut v1);
-    assert_eq!(v1, vec![0, 0, 0, 0, 0, 2, 2, 4]);
+    let v1 = vec![2, 0, 0, 0, 2, 0, 4, 0];
+    assert_eq!(
+        compactify(&v1, Direction::Right),
+        vec![0, 0, 0, 0, 0, 2, 2, 4]
+    );
+
+    let v2 = vec![0, 0, 2, 0, 2, 0, 4, 0];
+    assert_eq!(
+        compactify(&v2, Direction::Right),
+        vec![0, 0, 0, 0, 0, 2, 2, 4]
+    );
+
+    let v3 = vec![2, 2, 4, 0, 0, 0, 0, 0];
+    assert_eq!(
+        compactify(&v3, Direction::Right),
+        vec![0, 0, 0, 0, 0, 2, 2, 4]
+    );
+
+    let v4 = vec![2, 2, 4];
+    assert_eq!(compactify(&v4, Direction::Right), vec![2, 2, 4]);
 
-    let mut v2 = vec![0, 0, 2, 0, 2, 0, 4, 0];
-    compactify_row_right(&mut v2);
-    assert_eq!(v2, vec![0, 0, 0, 0, 0, 2, 2, 4]);
+    let v5 = vec![0, 2, 0, 0, 0, 2, 4, 4, 0, 2, 0, 2, 2];
+    assert_eq!(
+        compactify(&v5, Direction::Right),
+        vec![0, 0, 0, 0, 0, 0, 2, 2, 4, 4, 2, 2, 2]
+    );
+}
+
+#[test]
+fn test_shift_row_left() {
+    let mut cells = Matrix::new(vec![vec![2, 0, 0, 0, 2, 0, 4, 0]]);
+    assert_eq!(
+        shift_line(&mut cells.line_mut(Direction::Left, 0), &ClassicMerge),
+        4
+    );
+    assert_eq!(cells, vec![vec![4, 4, 0, 0, 0, 0, 0, 0]]);
 
-    let mut v3 = vec![2, 2, 4, 0, 0, 0, 0, 0];
-    compactify_row_right(&mut v3);
-    assert_eq!(v3, vec![0, 0, 0, 0, 0, 2, 2, 4]);
+    let mut cells = Matrix::new(vec![vec![2, 2, 2, 2, 8, 4, 4, 2]]);
+    assert_eq!(
+        shift_line(&mut cells.line_mut(Direction::Left, 0), &ClassicMerge),
+        16
+    );
+    assert_eq!(cells, vec![vec![4, 4, 8, 8, 2, 0, 0, 0]]);
+}
 
-    let mut v4 = vec![2, 2, 4];
-    compactify_row_right(&mut v4);
-    assert_eq!(v4, vec![2, 2, 4]);
+#[test]
+fn test_shift_row_right() {
+    let mut cells = Matrix::new(vec![vec![2, 0, 0, 0, 2, 0, 4, 0]]);
+    assert_eq!(
+        shift_line(&mut cells.line_mut(Direction::Right, 0), &ClassicMerge),
+        4
+    );
+    assert_eq!(cells, vec![vec![0, 0, 0, 0, 0, 0, 4, 4]]);
 
-    let mut v5 = vec![0, 2, 0, 0, 0, 2, 4, 4, 0, 2, 0, 2, 2];
-    compactify_row_right(&mut v5);
-    assert_eq!(v5, vec![0, 0, 0, 0, 0, 0, 2, 2, 4, 4, 2, 2, 2]);
+    assert_eq!(
+        apply_to_row(&[2, 2, 2, 2, 8, 4, 4, 2], Direction::Right, shift_line),
+        vec![0, 0, 0, 4, 4, 8, 8, 2]
+    );
 }
 
 #[test]
-fn test_transpose_square_matrix() {
-    let mut v = vec![
-        vec![1, 2, 3, 4],
-        vec![5, 6, 7, 8],
-        vec![9, 10, 11, 12],
-        vec![13, 14, 15, 16],
+fn test_shift_cells_matches_shift_board() {
+    let v1 = vec![
+        vec![2, 0, 2, 0],
+        vec![0, 4, 4, 2],
+        vec![2, 2, 2, 2],
+        vec![2, 4, 2, 4],
     ];
-    transpose(&mut v);
+    for direction in &board::DIRECTIONS {
+        let mut cells = Matrix::new(v1.clone());
+        let score = shift_unmasked(&mut cells, *direction, Movement::Slide, &ClassicMerge);
+        assert_eq!(
+            (cells.to_vec(), score.0),
+            board::shift_board(&v1, *direction)
+        );
+    }
+}
+
+#[test]
+fn test_shift_non_square_cells() {
+    let wide = vec![
+        vec![2, 0, 2, 4, 4],
+        vec![2, 4, 0, 4, 8],
+        vec![0, 4, 2, 0, 8],
+    ];
+    let tall: Vec<Vec<i32>> = (0..5)
+        .map(|j| wide.iter().map(|row| row[j]).collect())
+        .collect();
+    for v in &[wide.clone(), tall] {
+        for direction in &board::DIRECTIONS {
+            let mut cells = Matrix::new(v.clone());
+            let score = shift_unmasked(&mut cells, *direction, Movement::Slide, &ClassicMerge);
+            assert_eq!((cells.to_vec(), score.0), board::shift_board(v, *direction));
+        }
+    }
+
+    let mut cells = Matrix::new(wide);
+    assert_eq!(
+        shift_unmasked(&mut cells, Direction::Down, Movement::Slide, &ClassicMerge),
+        (40, vec![(0, 0), (0, 1), (0, 2), (0, 3), (0, 4)])
+    );
     assert_eq!(
-        v,
+        cells,
         vec![
-            vec![1, 5, 9, 13],
-            vec![2, 6, 10, 14],
-            vec![3, 7, 11, 15],
-            vec![4, 8, 12, 16]
+            vec![0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 4],
+            vec![4, 8, 4, 8, 16]
         ]
     );
 }
 
 #[test]
-fn test_transpose_identity_matrix() {
-    let mut v = vec![
-        vec![1, 0, 0, 0],
-        vec![0, 1, 0, 0],
-        vec![0, 0, 1, 0],
-        vec![0, 0, 0, 1],
+fn test_can_shift_matches_legal_moves() {
+    let boards = vec![
+        vec![vec![2, 4], vec![4, 2]],
+        vec![vec![2, 0], vec![4, 0]],
+        vec![vec![2, 4, 8], vec![2, 8, 4]],
+        vec![
+            vec![2, 4, 2, 4],
+            vec![4, 2, 4, 2],
+            vec![2, 4, 2, 4],
+            vec![4, 2, 8, 8],
+        ],
     ];
-    transpose(&mut v);
-    assert_eq!(
-        v,
+    for v in boards {
+        let cells = Matrix::new(v.clone());
+        let moves: Vec<Direction> = board::DIRECTIONS
+            .iter()
+            .copied()
+            .filter(|direction| can_shift_unmasked(&cells, *direction, &ClassicMerge))
+            .collect();
+        assert_eq!(moves, board::legal_moves(&v));
+    }
+}
+
+#[test]
+fn test_legal_moves_fast_path() {
+    use crate::random::Pcg32;
+    use crate::tile_generator::TileOption;
+
+    let board = |v: Vec<Vec<i32>>| {
+        let options = vec![TileOption {
+            value: 2,
+            probability: 100,
+        }];
+        let generator = TileGenerator::new(options, Pcg32::new(0)).unwrap();
+        let mask = Matrix::filled(v.len(), v[0].len(), true);
+        Board::from_cells(v, mask, generator, ClassicMerge)
+    };
+    let boards = vec![
         vec![
-            vec![1, 0, 0, 0],
-            vec![0, 1, 0, 0],
-            vec![0, 0, 1, 0],
-            vec![0, 0, 0, 1]
-        ]
+            vec![2, 4, 2, 4],
+            vec![4, 2, 4, 2],
+            vec![2, 4, 2, 4],
+            vec![4, 2, 8, 8],
+        ],
+        vec![
+            vec![0, 0, 0, 2],
+            vec![0, 0, 0, 4],
+            vec![0, 0, 0, 2],
+            vec![0, 0, 0, 4],
+        ],
+        vec![
+            vec![WALL, WALL, 2, 4],
+            vec![4, 2, 4, 2],
+            vec![2, 4, 2, 4],
+            vec![4, 2, 4, 2],
+        ],
+        vec![
+            vec![2, WALL, 0, 0],
+            vec![WALL, 0, 4, 0],
+            vec![0, 0, WALL, 0],
+            vec![0, 0, 0, 0],
+        ],
+    ];
+    for v in boards {
+        let board = board(v);
+        let moves: Vec<Direction> = board::DIRECTIONS
+            .iter()
+            .copied()
+            .filter(|direction| {
+                can_shift(
+                    board.cells(),
+                    board.mask(),
+                    *direction,
+                    Movement::Slide,
+                    &ClassicMerge,
+                )
+            })
+            .collect();
+        assert!(board.plain_4x4().is_some());
+        assert_eq!(board.legal_moves(Movement::Slide), moves);
+    }
+
+    let walled = board(vec![
+        vec![WALL, WALL, 2, 4],
+        vec![4, 2, 4, 2],
+        vec![2, 4, 2, 4],
+        vec![4, 2, 4, 2],
+    ]);
+    assert!(walled.legal_moves(Movement::Slide).is_empty());
+}
+
+#[test]
+fn test_step_line() {
+    use crate::merge_rule::ThreesMerge;
+
+    let step = |row: &[i32], direction| {
+        let mut cells = Matrix::new(vec![row.to_vec()]);
+        let score = step_line(&mut cells.line_mut(direction, 0), &ThreesMerge);
+        (cells.to_vec().remove(0), score)
+    };
+    assert_eq!(step(&[0, 3, 0, 6], Direction::Left), (vec![3, 0, 6, 0], 0));
+    assert_eq!(step(&[3, 1, 2, 6], Direction::Left), (vec![3, 3, 6, 0], 3));
+    assert_eq!(
+        step(&[6, 6, 3, 3], Direction::Left),
+        (vec![12, 3, 3, 0], 12)
+    );
+    assert_eq!(step(&[6, 6, 3, 3], Direction::Right), (vec![0, 6, 6, 6], 6));
+    assert_eq!(step(&[1, 1, 3, 0], Direction::Left), (vec![1, 1, 3, 0], 0));
+    assert_eq!(step(&[1, 1, 3, 0], Direction::Right), (vec![0, 1, 1, 3], 0));
+}
+
+#[test]
+fn test_step_cells_reports_edges() {
+    use crate::merge_rule::ThreesMerge;
+
+    let mut cells = Matrix::new(vec![vec![3, 0, 0], vec![1, 2, 0], vec![0, 0, 6]]);
+    let (score, edges) = shift_unmasked(&mut cells, Direction::Left, Movement::Step, &ThreesMerge);
+    assert_eq!((score, edges), (3, vec![(1, 2), (2, 2)]));
+    assert_eq!(cells, vec![vec![3, 0, 0], vec![3, 0, 0], vec![0, 6, 0]]);
+
+    let mut cells = Matrix::new(vec![vec![0, 3], vec![0, 0], vec![3, 0]]);
+    let (_, edges) = shift_unmasked(&mut cells, Direction::Down, Movement::Step, &ThreesMerge);
+    assert_eq!(edges, vec![(0, 1)]);
+}
+
+#[test]
+fn test_step_on_walled_line_spawns_at_segment_edge() {
+    use crate::random::Pcg32;
+    use crate::tile_generator::TileOption;
+
+    let options = vec![TileOption {
+        value: 2,
+        probability: 100,
+    }];
+    let generator = TileGenerator::new(options, Pcg32::new(0)).unwrap();
+    let cells = vec![vec![0, 2, WALL, 0, 0], vec![0, 0, 0, 0, 0]];
+    let mask = Matrix::filled(2, 5, true);
+    let mut board = Board::from_cells(cells, mask, generator, ClassicMerge);
+    let (_, edges) = board.shift(Direction::Left, Movement::Step);
+    assert_eq!(edges, vec![(0, 1)]);
+    assert_eq!(board.add_edge_tile(&edges), Some((0, 1, 2)));
+    assert_eq!(
+        board.cells(),
+        &vec![vec![2, 2, WALL, 0, 0], vec![0, 0, 0, 0, 0]]
+    );
+
+    // With a mask, the edge is the last active cell of the segment.
+    let generator = TileGenerator::new(
+        vec![TileOption {
+            value: 2,
+            probability: 100,
+        }],
+        Pcg32::new(0),
+    )
+    .unwrap();
+    let cells = vec![vec![0, 2, 0, WALL, 4]];
+    let mask = Matrix::new(vec![vec![true, true, false, true, true]]);
+    let mut board = Board::from_cells(cells, mask, generator, ClassicMerge);
+    let (_, edges) = board.shift(Direction::Left, Movement::Step);
+    assert_eq!(edges, vec![(0, 1)]);
+}
+
+#[test]
+fn test_base_merge_lines() {
+    use crate::merge_rule::BaseMerge;
+
+    let rule = BaseMerge::new(3).unwrap();
+    let shift = |row: &[i32], movement| {
+        let mut cells = Matrix::new(vec![row.to_vec()]);
+        let (score, _) = shift_unmasked(&mut cells, Direction::Left, movement, &rule);
+        (cells.to_vec().remove(0), score)
+    };
+    assert_eq!(
+        shift(&[3, 3, 0, 3, 3, 3, 3, 3], Movement::Slide),
+        (vec![9, 9, 3, 0, 0, 0, 0, 0], 18)
+    );
+    assert_eq!(
+        shift(&[3, 3, 9, 9, 9, 0], Movement::Slide),
+        (vec![3, 3, 27, 0, 0, 0], 27)
     );
+    assert_eq!(
+        shift(&[1, 3, 3, 3, 3, 9], Movement::Step),
+        (vec![1, 9, 3, 9, 0, 0], 9)
+    );
+
+    let stuck = Matrix::new(vec![vec![3, 3, 9, 9], vec![9, 9, 3, 3]]);
+    assert!(!can_shift_unmasked(&stuck, Direction::Left, &rule));
+    assert!(!can_shift_unmasked(&stuck, Direction::Up, &rule));
+    assert!(can_shift_unmasked(&stuck, Direction::Left, &ClassicMerge));
+    let run = Matrix::new(vec![vec![3, 9, 9, 9], vec![9, 3, 3, 1]]);
+    assert!(can_shift_unmasked(&run, Direction::Right, &rule));
+    assert!(!can_shift_unmasked(&run, Direction::Down, &rule));
+}
+
+#[test]
+fn test_walls_split_lines() {
+    assert_eq!(segments([-1, 2, 2, -1, -1, 4].iter()), vec![(1, 3), (5, 6)]);
+    assert_eq!(segments([2, 0].iter()), vec![(0, 2)]);
+
+    let mut cells = Matrix::new(vec![vec![2, 0, -1, 0, 2, 2], vec![0, 0, 2, -1, 2, 0]]);
+    let (score, moved) =
+        shift_unmasked(&mut cells, Direction::Left, Movement::Slide, &ClassicMerge);
+    assert_eq!((score, moved), (4, vec![(0, 5), (1, 2)]));
+    assert_eq!(
+        cells,
+        vec![vec![2, 0, -1, 4, 0, 0], vec![2, 0, 0, -1, 2, 0]]
+    );
+
+    let stuck = Matrix::new(vec![vec![2, -1, 2], vec![4, -1, 4]]);
+    assert!(!can_shift_unmasked(&stuck, Direction::Left, &ClassicMerge));
+    assert!(!can_shift_unmasked(&stuck, Direction::Right, &ClassicMerge));
+    let mut cells = Matrix::new(vec![vec![2, -1, 0], vec![0, -1, 4]]);
+    let (_, moved) = shift_unmasked(&mut cells, Direction::Down, Movement::Step, &ClassicMerge);
+    assert_eq!(moved, vec![(0, 0)]);
+    assert_eq!(cells, vec![vec![0, -1, 0], vec![2, -1, 4]]);
+}
+
+#[test]
+fn test_wrap_line() {
+    use crate::merge_rule::BaseMerge;
+
+    let wrap = |row: &[i32], direction| {
+        let mut cells = Matrix::new(vec![row.to_vec()]);
+        let mask = unmasked(&cells);
+        let can = can_shift(&cells, &mask, direction, Movement::Wrap, &ClassicMerge);
+        let (score, _) = shift_cells(&mut cells, &mask, direction, Movement::Wrap, &ClassicMerge);
+        (cells.to_vec().remove(0), score, can)
+    };
+    assert_eq!(
+        wrap(&[2, 0, 4, 2], Direction::Left),
+        (vec![4, 4, 0, 0], 4, true)
+    );
+    assert_eq!(
+        wrap(&[2, 4, 0, 2], Direction::Right),
+        (vec![0, 0, 4, 4], 4, true)
+    );
+    assert_eq!(
+        wrap(&[2, 4, 8, 2], Direction::Left),
+        (vec![4, 4, 8, 0], 4, true)
+    );
+    assert_eq!(
+        wrap(&[2, 2, 4, 2], Direction::Left),
+        (vec![4, 2, 4, 0], 4, true)
+    );
+    assert_eq!(
+        wrap(&[2, 4, 8, 16], Direction::Left),
+        (vec![2, 4, 8, 16], 0, false)
+    );
+    assert_eq!(
+        wrap(&[2, 4, -1, 2], Direction::Left),
+        (vec![2, 4, -1, 2], 0, false)
+    );
+    assert!(!can_shift_unmasked(
+        &Matrix::new(vec![vec![2, 4, 8, 2]]),
+        Direction::Left,
+        &ClassicMerge
+    ));
+
+    let rule = BaseMerge::new(3).unwrap();
+    let mut cells = Matrix::new(vec![vec![3, 9, 3, 3]]);
+    let mask = unmasked(&cells);
+    assert!(can_shift(
+        &cells,
+        &mask,
+        Direction::Left,
+        Movement::Wrap,
+        &rule
+    ));
+    shift_cells(&mut cells, &mask, Direction::Left, Movement::Wrap, &rule);
+    assert_eq!(cells, vec![vec![9, 9, 0, 0]]);
+}
+
+#[test]
+fn test_shift_segments_in_place() {
+    use crate::merge_rule::FibonacciMerge;
+
+    let mut cells = Matrix::new(vec![vec![2, 2, 0, 4], vec![0, 2, -1, 4], vec![2, 0, 8, 8]]);
+    let score = shift_segments(
+        &mut cells.line_mut(Direction::Down, 1),
+        Movement::Slide,
+        &ClassicMerge,
+    );
+    assert_eq!(score, 4);
+    assert_eq!(
+        cells,
+        vec![vec![2, 0, 0, 4], vec![0, 0, -1, 4], vec![2, 4, 8, 8]]
+    );
+
+    // All lines of a move are borrowed at once and shifted through the views.
+    let score: i32 = cells
+        .lines_mut(Direction::Up)
+        .map(|mut line| shift_segments(&mut line, Movement::Step, &ClassicMerge))
+        .sum();
+    assert_eq!(score, 8);
+    assert_eq!(
+        cells,
+        vec![vec![2, 0, 0, 8], vec![2, 4, -1, 8], vec![0, 0, 8, 0]]
+    );
+
+    let mut values = [0, 3, 0, 5, 8, -1, 2];
+    let score = shift_values(&mut values, Movement::Slide, &FibonacciMerge);
+    assert_eq!((score, values), (8, [8, 8, 0, 0, 0, -1, 2]));
 }
@@ -1,6 +1,9 @@
 use rand::Rng;
+use std::fmt;
 use std::ops::Range;
+use std::str::FromStr;
 
+#[derive(Clone)]
 struct SimpleGenerator<R: Rng> {
     rng: R,
 }
@@ -10,6 +13,70 @@ pub trait RandomNumberGenerator {
     fn next_in_range(&mut self, range: Range<i32>) -> i32;
 }
 
+/// Generators whose internal state can be captured and restored later, so a
+/// restored generator produces exactly the values the original would have.
+pub trait SnapshotGenerator: RandomNumberGenerator {
+    fn save_state(&self) -> RngState;
+    fn restore_state(&mut self, state: &RngState) -> Result<(), String>;
+}
+
+/// Raw generator state. Formats as dash separated 16 digit hex words.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RngState {
+    words: Vec<u64>,
+}
+
+impl RngState {
+    pub fn new(words: Vec<u64>) -> RngState {
+        RngState { words }
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+}
+
+impl fmt::Display for RngState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> = self
+            .words
+            .iter()
+            .map(|word| format!("{:016x}", word))
+            .collect();
+        write!(f, "{}", words.join("-"))
+    }
+}
+
+impl FromStr for RngState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RngState, String> {
+        let words = s
+            .split('-')
+            .map(|word| {
+                u64::from_str_radix(word, 16)
+                    .map_err(|err| format!("Invalid generator state word {:?}: {}", word, err))
+            })
+            .collect::<Result<Vec<u64>, String>>()?;
+        Ok(RngState { words })
+    }
+}
+
+#[cfg(test)]
+mockall::mock! {
+    pub RandomNumberGenerator {}
+
+    impl Clone for RandomNumberGenerator {
+        fn clone(&self) -> Self;
+    }
+
+    impl RandomNumberGenerator for RandomNumberGenerator {
+        fn next_float(&mut self) -> f64;
+        fn next_in_range(&mut self, range: Range<i32>) -> i32;
+    }
+}
+
 impl<R: Rng> RandomNumberGenerator for SimpleGenerator<R> {
     fn next_float(&mut self) -> f64 {
         self.rng.gen()
@@ -20,7 +87,84 @@ impl<R: Rng> RandomNumberGenerator for SimpleGenerator<R> {
     }
 }
 
-pub fn create_simple_generator() -> impl RandomNumberGenerator {
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+const PCG_DEFAULT_STREAM: u64 = 0xda3e39cb94b95bdb;
+
+/// PCG32 (PCG-XSH-RR with 64-bit state and 32-bit output) as described at
+/// https://www.pcg-random.org. Seeding follows the reference `pcg32_srandom_r`,
+/// floats take the top 53 bits of two outputs and ranges use rejection
+/// sampling, so a seed produces the same values on every platform.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    pub fn new(seed: u64) -> Pcg32 {
+        Pcg32::with_stream(seed, PCG_DEFAULT_STREAM)
+    }
+
+    pub fn with_stream(seed: u64, stream: u64) -> Pcg32 {
+        let mut pcg = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        pcg.next_u32();
+        pcg.state = pcg.state.wrapping_add(seed);
+        pcg.next_u32();
+        pcg
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state
+            .wrapping_mul(PCG_MULTIPLIER)
+            .wrapping_add(self.increment);
+        let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rotation = (old_state >> 59) as u32;
+        xor_shifted.rotate_right(rotation)
+    }
+}
+
+impl RandomNumberGenerator for Pcg32 {
+    fn next_float(&mut self) -> f64 {
+        let high = self.next_u32() as u64;
+        let low = self.next_u32() as u64;
+        (((high << 32) | low) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn next_in_range(&mut self, range: Range<i32>) -> i32 {
+        assert!(range.start < range.end, "Cannot sample empty range");
+        let span = range.end.wrapping_sub(range.start) as u32;
+        let threshold = span.wrapping_neg() % span;
+        loop {
+            let r = self.next_u32();
+            if r >= threshold {
+                return range.start.wrapping_add((r % span) as i32);
+            }
+        }
+    }
+}
+
+impl SnapshotGenerator for Pcg32 {
+    fn save_state(&self) -> RngState {
+        RngState::new(vec![self.state, self.increment])
+    }
+
+    fn restore_state(&mut self, state: &RngState) -> Result<(), String> {
+        match *state.words() {
+            [state, increment] if increment % 2 == 1 => {
+                self.state = state;
+                self.increment = increment;
+                Ok(())
+            }
+            _ => Err(format!("Invalid PCG32 state: {}", state)),
+        }
+    }
+}
+
+pub fn create_simple_generator() -> impl RandomNumberGenerator + Clone {
     SimpleGenerator {
         rng: rand::thread_rng(),
     }
@@ -43,3 +187,69 @@ fn test_next_in_range() {
     let random_int = srng.next_in_range(10..25);
     assert!((10..25).contains(&random_int));
 }
+
+#[test]
+fn test_pcg32_reference_output() {
+    let mut pcg = Pcg32::with_stream(42, 54);
+    let expected = [
+        0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e,
+    ];
+    for value in expected.iter() {
+        assert_eq!(pcg.next_u32(), *value);
+    }
+}
+
+#[test]
+fn test_pcg32_same_seed_same_sequence() {
+    let mut first = Pcg32::new(2048);
+    let mut second = Pcg32::new(2048);
+    let mut other = Pcg32::new(4096);
+    let first_values: Vec<i32> = (0..32).map(|_| first.next_in_range(0..16)).collect();
+    let second_values: Vec<i32> = (0..32).map(|_| second.next_in_range(0..16)).collect();
+    let other_values: Vec<i32> = (0..32).map(|_| other.next_in_range(0..16)).collect();
+    assert_eq!(first_values, second_values);
+    assert_ne!(first_values, other_values);
+}
+
+#[test]
+fn test_pcg32_next_float() {
+    let mut pcg = Pcg32::new(7);
+    for _ in 0..1000 {
+        let random_float = pcg.next_float();
+        assert!((0.0..1.0).contains(&random_float));
+    }
+}
+
+#[test]
+fn test_pcg32_next_in_range() {
+    let mut pcg = Pcg32::new(7);
+    for _ in 0..1000 {
+        let random_int = pcg.next_in_range(-3..5);
+        assert!((-3..5).contains(&random_int));
+    }
+    assert_eq!(pcg.next_in_range(4..5), 4);
+}
+
+#[test]
+fn test_pcg32_save_and_restore_state() {
+    let mut pcg = Pcg32::new(2048);
+    pcg.next_u32();
+    let state = pcg.save_state();
+    let expected: Vec<u32> = (0..8).map(|_| pcg.next_u32()).collect();
+
+    let mut restored = Pcg32::new(0);
+    restored.restore_state(&state).unwrap();
+    let actual: Vec<u32> = (0..8).map(|_| restored.next_u32()).collect();
+    assert_eq!(actual, expected);
+
+    assert!(restored.restore_state(&RngState::new(vec![1, 2])).is_err());
+    assert!(restored.restore_state(&RngState::new(vec![1])).is_err());
+}
+
+#[test]
+fn test_rng_state_string_round_trip() {
+    let state = RngState::new(vec![0x0123456789abcdef, 1]);
+    assert_eq!(state.to_string(), "0123456789abcdef-0000000000000001");
+    assert_eq!(state.to_string().parse::<RngState>(), Ok(state));
+    assert!("0123-xyz".parse::<RngState>().is_err());
+}
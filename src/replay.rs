@@ -0,0 +1,1116 @@
+use crate::board::{Direction, Movement};
+use crate::game::{Game, GameConfig, MoveOutcome, MAX_SIZE, MIN_SIZE};
+use crate::merge_rule::{AnyMerge, BaseMerge, MergeRule};
+use crate::random::Pcg32;
+use crate::tile_generator::TileOption;
+use std::convert::TryFrom;
+
+pub const REPLAY_VERSION: u32 = 6;
+
+const TEXT_HEADER: &str = "2048-replay";
+const BINARY_MAGIC: &[u8; 4] = b"2KRP";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: GameConfig,
+    pub rule: AnyMerge,
+    pub moves: Vec<Direction>,
+    pub final_score: i32,
+    pub final_cells: Vec<Vec<i32>>,
+}
+
+impl Replay {
+    pub fn record(seed: u64, config: GameConfig, moves: &[Direction]) -> Result<Replay, String> {
+        Replay::record_with_rule(seed, config, AnyMerge::Classic, moves)
+    }
+
+    pub fn record_with_rule(
+        seed: u64,
+        config: GameConfig,
+        rule: AnyMerge,
+        moves: &[Direction],
+    ) -> Result<Replay, String> {
+        let mut replay = Replay {
+            seed,
+            config,
+            rule,
+            moves: moves.to_vec(),
+            final_score: 0,
+            final_cells: vec![],
+        };
+        let game = replay.simulate()?;
+        replay.final_score = game.score();
+        replay.final_cells = game.cells().to_vec();
+        Ok(replay)
+    }
+
+    pub fn from_game<M>(seed: u64, game: &Game<Pcg32, M>) -> Result<Replay, String>
+    where
+        M: MergeRule + Clone + Into<AnyMerge>,
+    {
+        if game.move_history().len() != game.moves() {
+            return Err(
+                "Game was loaded from a save, so its earlier moves are unknown".to_string(),
+            );
+        }
+        Replay::record_with_rule(
+            seed,
+            game.config().clone(),
+            game.rule().clone().into(),
+            game.move_history(),
+        )
+    }
+
+    pub fn simulate(&self) -> Result<Game<Pcg32, AnyMerge>, String> {
+        let mut game = Game::with_rule(self.config.clone(), Pcg32::new(self.seed), self.rule)?;
+        for (index, direction) in self.moves.iter().enumerate() {
+            match game.make_move(*direction) {
+                MoveOutcome::Moved { .. } => {}
+                MoveOutcome::Illegal => {
+                    return Err(format!(
+                        "Move {} ({:?}) does not change the board",
+                        index + 1,
+                        direction
+                    ))
+                }
+                MoveOutcome::GameOver => {
+                    return Err(format!(
+                        "Move {} ({:?}) was made after the game ended",
+                        index + 1,
+                        direction
+                    ))
+                }
+            }
+        }
+        Ok(game)
+    }
+
+    pub fn to_text(&self) -> String {
+        let tiles: Vec<String> = self
+            .config
+            .tile_options
+            .iter()
+            .map(|option| format!("{}:{}", option.value, option.probability))
+            .collect();
+        let moves: String = self.moves.iter().map(|d| direction_to_char(*d)).collect();
+        let board: Vec<String> = self
+            .final_cells
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            })
+            .collect();
+        let mut text = String::new();
+        text += &format!("{} {}\n", TEXT_HEADER, REPLAY_VERSION);
+        text += &format!("seed {}\n", self.seed);
+        text += &format!("rows {}\n", self.config.rows);
+        text += &format!("cols {}\n", self.config.cols);
+        text += &format!("tiles {}\n", tiles.join(" "));
+        text += &format!("initial-tiles {}\n", self.config.initial_tiles);
+        text += &format!("win-target {}\n", self.config.win_target);
+        text += &format!("endless {}\n", self.config.endless);
+        match self.config.undo_limit {
+            Some(limit) => text += &format!("undo-limit {}\n", limit),
+            None => text += "undo-limit none\n",
+        }
+        text += &format!("undo-penalty {}\n", self.config.undo_penalty);
+        text += &format!("movement {}\n", movement_to_str(self.config.movement));
+        match &self.config.tile_bag {
+            Some(bag) => {
+                let bag: Vec<String> = bag.iter().map(|tile| tile.to_string()).collect();
+                text += &format!("tile-bag {}\n", bag.join(" "));
+            }
+            None => text += "tile-bag none\n",
+        }
+        if self.config.walls.is_empty() {
+            text += "walls none\n";
+        } else {
+            let walls: Vec<String> = self
+                .config
+                .walls
+                .iter()
+                .map(|(i, j)| format!("{},{}", i, j))
+                .collect();
+            text += &format!("walls {}\n", walls.join(" "));
+        }
+        match &self.config.mask {
+            Some(mask) => {
+                let rows: Vec<String> = mask
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|active| if *active { '#' } else { '.' })
+                            .collect()
+                    })
+                    .collect();
+                text += &format!("mask {}\n", rows.join("/"));
+            }
+            None => text += "mask none\n",
+        }
+        text += &format!("rule {}\n", rule_to_str(self.rule));
+        text += &format!("moves {}\n", moves);
+        text += &format!("score {}\n", self.final_score);
+        text += &format!("board {}\n", board.join("/"));
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = lines.next().ok_or("Replay is empty")?;
+        let version = match header.split_whitespace().collect::<Vec<&str>>()[..] {
+            [TEXT_HEADER, version] => check_version(parse_number(version, "version")?)?,
+            _ => return Err(format!("Invalid replay header: {:?}", header)),
+        };
+
+        let mut fields = vec![];
+        for line in lines {
+            let line = line.trim();
+            let (key, value) = match line.find(' ') {
+                Some(index) => (&line[..index], line[index + 1..].trim()),
+                None => (line, ""),
+            };
+            fields.push((key, value));
+        }
+        let field = |name: &str| -> Result<&str, String> {
+            fields
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+                .ok_or(format!("Replay is missing the {:?} field", name))
+        };
+
+        let mut tile_options = vec![];
+        for tile in field("tiles")?.split_whitespace() {
+            let mut parts = tile.splitn(2, ':');
+            let value = parts.next().unwrap_or("");
+            let probability = parts.next().unwrap_or("");
+            tile_options.push(TileOption {
+                value: parse_number(value, "tile value")?,
+                probability: parse_number(probability, "tile probability")?,
+            });
+        }
+        let undo_limit = match field("undo-limit")? {
+            "none" => None,
+            limit => Some(parse_number(limit, "undo limit")?),
+        };
+        let (rows, cols) = if version == 1 {
+            let size = parse_number(field("size")?, "size")?;
+            (size, size)
+        } else {
+            (
+                parse_number(field("rows")?, "rows")?,
+                parse_number(field("cols")?, "cols")?,
+            )
+        };
+        let (movement, tile_bag) = if version < 3 {
+            (Movement::Slide, None)
+        } else {
+            let tile_bag = match field("tile-bag")? {
+                "none" => None,
+                bag => Some(
+                    bag.split_whitespace()
+                        .map(|tile| parse_number(tile, "bag tile"))
+                        .collect::<Result<Vec<i32>, String>>()?,
+                ),
+            };
+            (movement_from_str(field("movement")?)?, tile_bag)
+        };
+        let mut walls = vec![];
+        if version >= 4 && field("walls")? != "none" {
+            for wall in field("walls")?.split_whitespace() {
+                let mut parts = wall.splitn(2, ',');
+                let i = parts.next().unwrap_or("");
+                let j = parts.next().unwrap_or("");
+                walls.push((
+                    parse_number(i, "wall row")?,
+                    parse_number(j, "wall column")?,
+                ));
+            }
+        }
+        let mask = match field("mask") {
+            _ if version < 5 => None,
+            Ok("none") => None,
+            value => Some(parse_mask(value?)?),
+        };
+        let rule = match field("rule") {
+            _ if version < 6 => AnyMerge::Classic,
+            value => rule_from_str(value?)?,
+        };
+        let config = GameConfig {
+            rows,
+            cols,
+            tile_options,
+            initial_tiles: parse_number(field("initial-tiles")?, "initial tiles")?,
+            win_target: parse_number(field("win-target")?, "win target")?,
+            endless: parse_number(field("endless")?, "endless")?,
+            undo_limit,
+            undo_penalty: parse_number(field("undo-penalty")?, "undo penalty")?,
+            movement,
+            tile_bag,
+            walls,
+            mask,
+        };
+        let moves = field("moves")?
+            .chars()
+            .map(direction_from_char)
+            .collect::<Result<Vec<Direction>, String>>()?;
+        let final_cells = field("board")?
+            .split('/')
+            .map(|row| {
+                row.split_whitespace()
+                    .map(|cell| parse_number(cell, "board cell"))
+                    .collect::<Result<Vec<i32>, String>>()
+            })
+            .collect::<Result<Vec<Vec<i32>>, String>>()?;
+
+        Ok(Replay {
+            seed: parse_number(field("seed")?, "seed")?,
+            config,
+            rule,
+            moves,
+            final_score: parse_number(field("score")?, "score")?,
+            final_cells,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = BINARY_MAGIC.to_vec();
+        write_unsigned(&mut bytes, REPLAY_VERSION as u64);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        write_unsigned(&mut bytes, self.config.rows as u64);
+        write_unsigned(&mut bytes, self.config.cols as u64);
+        write_unsigned(&mut bytes, self.config.tile_options.len() as u64);
+        for option in &self.config.tile_options {
+            write_signed(&mut bytes, option.value as i64);
+            write_signed(&mut bytes, option.probability as i64);
+        }
+        write_unsigned(&mut bytes, self.config.initial_tiles as u64);
+        write_signed(&mut bytes, self.config.win_target as i64);
+        bytes.push(self.config.endless as u8);
+        write_unsigned(
+            &mut bytes,
+            self.config.undo_limit.map_or(0, |l| l as u64 + 1),
+        );
+        write_signed(&mut bytes, self.config.undo_penalty as i64);
+        bytes.push(movement_to_bits(self.config.movement));
+        match &self.config.tile_bag {
+            Some(bag) => {
+                write_unsigned(&mut bytes, bag.len() as u64 + 1);
+                for tile in bag {
+                    write_signed(&mut bytes, *tile as i64);
+                }
+            }
+            None => write_unsigned(&mut bytes, 0),
+        }
+        write_unsigned(&mut bytes, self.config.walls.len() as u64);
+        for (i, j) in &self.config.walls {
+            write_unsigned(&mut bytes, *i as u64);
+            write_unsigned(&mut bytes, *j as u64);
+        }
+        match &self.config.mask {
+            Some(mask) => {
+                bytes.push(1);
+                let cells: Vec<bool> = mask.iter().flatten().copied().collect();
+                for chunk in cells.chunks(8) {
+                    let mut packed = 0;
+                    for (index, active) in chunk.iter().enumerate() {
+                        packed |= (*active as u8) << index;
+                    }
+                    bytes.push(packed);
+                }
+            }
+            None => bytes.push(0),
+        }
+        match self.rule {
+            AnyMerge::Classic => bytes.push(0),
+            AnyMerge::Fibonacci => bytes.push(1),
+            AnyMerge::Threes => bytes.push(2),
+            AnyMerge::Base(rule) => {
+                bytes.push(3);
+                write_unsigned(&mut bytes, rule.base() as u64);
+            }
+        }
+        write_unsigned(&mut bytes, self.moves.len() as u64);
+        for chunk in self.moves.chunks(4) {
+            let mut packed = 0;
+            for (index, direction) in chunk.iter().enumerate() {
+                packed |= direction_to_bits(*direction) << (2 * index);
+            }
+            bytes.push(packed);
+        }
+        write_signed(&mut bytes, self.final_score as i64);
+        write_unsigned(&mut bytes, self.final_cells.len() as u64);
+        write_unsigned(
+            &mut bytes,
+            self.final_cells.first().map_or(0, |row| row.len()) as u64,
+        );
+        for cell in self.final_cells.iter().flatten() {
+            write_signed(&mut bytes, *cell as i64);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, String> {
+        if !bytes.starts_with(BINARY_MAGIC) {
+            return Err("Invalid replay magic bytes".to_string());
+        }
+        let mut reader = ByteReader {
+            bytes,
+            position: BINARY_MAGIC.len(),
+        };
+        let version = check_version(reader.unsigned()?)?;
+        let seed = u64::from_le_bytes(reader.array()?);
+        let board_rows = reader.size()?;
+        let board_cols = if version == 1 {
+            board_rows
+        } else {
+            reader.size()?
+        };
+        let option_count = reader.unsigned()?;
+        let mut tile_options = vec![];
+        for _ in 0..option_count {
+            tile_options.push(TileOption {
+                value: reader.signed_as("tile value")?,
+                probability: reader.signed_as("tile probability")?,
+            });
+        }
+        let initial_tiles = reader.unsigned_as("initial tile count")?;
+        let win_target = reader.signed_as("win target")?;
+        let endless = reader.byte()? != 0;
+        let undo_limit = match reader.unsigned()? {
+            0 => None,
+            limit => Some(usize::try_from(limit - 1).map_err(|_| {
+                format!("Replay contains an out of range undo limit: {}", limit - 1)
+            })?),
+        };
+        let undo_penalty = reader.signed_as("undo penalty")?;
+        let (movement, tile_bag) = if version < 3 {
+            (Movement::Slide, None)
+        } else {
+            let movement = movement_from_bits(reader.byte()?)?;
+            let tile_bag = match reader.unsigned()? {
+                0 => None,
+                count => Some(
+                    (1..count)
+                        .map(|_| reader.signed_as("tile"))
+                        .collect::<Result<Vec<i32>, String>>()?,
+                ),
+            };
+            (movement, tile_bag)
+        };
+        let mut walls = vec![];
+        if version >= 4 {
+            for _ in 0..reader.unsigned()? {
+                walls.push((reader.unsigned_as("wall")?, reader.unsigned_as("wall")?));
+            }
+        }
+        let mut mask = None;
+        if version >= 5 && reader.byte()? != 0 {
+            let mut cells = vec![];
+            while cells.len() < board_rows * board_cols {
+                let packed = reader.byte()?;
+                for index in 0..8.min(board_rows * board_cols - cells.len()) {
+                    cells.push(packed >> index & 1 != 0);
+                }
+            }
+            mask = Some(
+                cells
+                    .chunks(board_cols.max(1))
+                    .map(|row| row.to_vec())
+                    .collect(),
+            );
+        }
+        let rule = match version {
+            1..=5 => AnyMerge::Classic,
+            _ => match reader.byte()? {
+                0 => AnyMerge::Classic,
+                1 => AnyMerge::Fibonacci,
+                2 => AnyMerge::Threes,
+                3 => AnyMerge::Base(BaseMerge::new(reader.unsigned_as("merge base")?)?),
+                bits => return Err(format!("Invalid merge rule in replay: {}", bits)),
+            },
+        };
+        let move_count = reader.unsigned()?;
+        if move_count > reader.remaining() as u64 * 4 {
+            return Err(format!(
+                "Replay claims {} moves, but only {} bytes are left",
+                move_count,
+                reader.remaining()
+            ));
+        }
+        let move_count = move_count as usize;
+        let mut moves = vec![];
+        while moves.len() < move_count {
+            let packed = reader.byte()?;
+            for index in 0..4.min(move_count - moves.len()) {
+                moves.push(direction_from_bits((packed >> (2 * index)) & 0b11));
+            }
+        }
+        let final_score = reader.signed_as("score")?;
+        let rows = reader.size()?;
+        let cols = reader.size()?;
+        let mut final_cells = vec![vec![0; cols]; rows];
+        for cell in final_cells.iter_mut().flatten() {
+            *cell = reader.signed_as("cell")?;
+        }
+        if reader.position != bytes.len() {
+            return Err(format!(
+                "Unexpected {} trailing bytes in replay",
+                bytes.len() - reader.position
+            ));
+        }
+
+        Ok(Replay {
+            seed,
+            config: GameConfig {
+                rows: board_rows,
+                cols: board_cols,
+                tile_options,
+                initial_tiles,
+                win_target,
+                endless,
+                undo_limit,
+                undo_penalty,
+                movement,
+                tile_bag,
+                walls,
+                mask,
+            },
+            rule,
+            moves,
+            final_score,
+            final_cells,
+        })
+    }
+}
+
+pub fn verify(replay: &Replay) -> Result<(), String> {
+    let game = replay.simulate()?;
+    if game.score() != replay.final_score {
+        return Err(format!(
+            "Replay claims a score of {}, but the moves produce {}",
+            replay.final_score,
+            game.score()
+        ));
+    }
+    if game.cells() != &replay.final_cells[..] {
+        return Err(format!(
+            "Replay claims a final board of {:?}, but the moves produce {:?}",
+            replay.final_cells,
+            game.cells()
+        ));
+    }
+    Ok(())
+}
+
+/// Version 1 replays only support square boards and store a single size.
+/// Versions before 3 always slide and draw tiles from the options, versions
+/// before 4 have no walls, versions before 5 are rectangular and versions
+/// before 6 always use the classic merge rule.
+fn check_version(version: u64) -> Result<u32, String> {
+    if version == 0 || version > REPLAY_VERSION as u64 {
+        return Err(format!(
+            "Unsupported replay version {}. Supported versions: 1 to {}",
+            version, REPLAY_VERSION
+        ));
+    }
+    Ok(version as u32)
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {} in replay: {:?}", name, value))
+}
+
+fn direction_to_char(direction: Direction) -> char {
+    match direction {
+        Direction::Left => 'L',
+        Direction::Right => 'R',
+        Direction::Up => 'U',
+        Direction::Down => 'D',
+    }
+}
+
+fn direction_from_char(c: char) -> Result<Direction, String> {
+    match c {
+        'L' => Ok(Direction::Left),
+        'R' => Ok(Direction::Right),
+        'U' => Ok(Direction::Up),
+        'D' => Ok(Direction::Down),
+        _ => Err(format!("Invalid move in replay: {:?}", c)),
+    }
+}
+
+fn parse_mask(value: &str) -> Result<Vec<Vec<bool>>, String> {
+    value
+        .split('/')
+        .map(|row| {
+            row.chars()
+                .map(|c| match c {
+                    '#' => Ok(true),
+                    '.' => Ok(false),
+                    _ => Err(format!("Invalid mask cell in replay: {:?}", c)),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn rule_to_str(rule: AnyMerge) -> String {
+    match rule {
+        AnyMerge::Classic => "classic".to_string(),
+        AnyMerge::Fibonacci => "fibonacci".to_string(),
+        AnyMerge::Threes => "threes".to_string(),
+        AnyMerge::Base(rule) => format!("base {}", rule.base()),
+    }
+}
+
+fn rule_from_str(value: &str) -> Result<AnyMerge, String> {
+    match value.split_whitespace().collect::<Vec<&str>>()[..] {
+        ["classic"] => Ok(AnyMerge::Classic),
+        ["fibonacci"] => Ok(AnyMerge::Fibonacci),
+        ["threes"] => Ok(AnyMerge::Threes),
+        ["base", base] => Ok(AnyMerge::Base(BaseMerge::new(parse_number(base, "base")?)?)),
+        _ => Err(format!("Invalid merge rule in replay: {:?}", value)),
+    }
+}
+
+fn movement_to_str(movement: Movement) -> &'static str {
+    match movement {
+        Movement::Slide => "slide",
+        Movement::Step => "step",
+        Movement::Wrap => "wrap",
+    }
+}
+
+fn movement_from_str(value: &str) -> Result<Movement, String> {
+    match value {
+        "slide" => Ok(Movement::Slide),
+        "step" => Ok(Movement::Step),
+        "wrap" => Ok(Movement::Wrap),
+        _ => Err(format!("Invalid movement in replay: {:?}", value)),
+    }
+}
+
+fn movement_to_bits(movement: Movement) -> u8 {
+    match movement {
+        Movement::Slide => 0,
+        Movement::Step => 1,
+        Movement::Wrap => 2,
+    }
+}
+
+fn movement_from_bits(bits: u8) -> Result<Movement, String> {
+    match bits {
+        0 => Ok(Movement::Slide),
+        1 => Ok(Movement::Step),
+        2 => Ok(Movement::Wrap),
+        _ => Err(format!("Invalid movement in replay: {}", bits)),
+    }
+}
+
+fn direction_to_bits(direction: Direction) -> u8 {
+    match direction {
+        Direction::Left => 0,
+        Direction::Right => 1,
+        Direction::Up => 2,
+        Direction::Down => 3,
+    }
+}
+
+fn direction_from_bits(bits: u8) -> Direction {
+    match bits {
+        0 => Direction::Left,
+        1 => Direction::Right,
+        2 => Direction::Up,
+        _ => Direction::Down,
+    }
+}
+
+fn write_unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_signed(bytes: &mut Vec<u8>, value: i64) {
+    write_unsigned(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or("Replay ended unexpectedly")?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn array(&mut self) -> Result<[u8; 8], String> {
+        let mut array = [0; 8];
+        for byte in array.iter_mut() {
+            *byte = self.byte()?;
+        }
+        Ok(array)
+    }
+
+    fn unsigned(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Replay contains an oversized number".to_string())
+    }
+
+    fn signed(&mut self) -> Result<i64, String> {
+        let value = self.unsigned()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    /// An unsigned number that should fit in `T`. `what` names the number in
+    /// the error.
+    fn unsigned_as<T: TryFrom<u64>>(&mut self, what: &str) -> Result<T, String> {
+        let value = self.unsigned()?;
+        T::try_from(value)
+            .map_err(|_| format!("Replay contains an out of range {}: {}", what, value))
+    }
+
+    fn signed_as<T: TryFrom<i64>>(&mut self, what: &str) -> Result<T, String> {
+        let value = self.signed()?;
+        T::try_from(value)
+            .map_err(|_| format!("Replay contains an out of range {}: {}", what, value))
+    }
+
+    fn size(&mut self) -> Result<usize, String> {
+        let size = self.unsigned()?;
+        if !(MIN_SIZE as u64..=MAX_SIZE as u64).contains(&size) {
+            return Err(format!(
+                "Replay contains a board size of {}. Supported sizes: {} to {}",
+                size, MIN_SIZE, MAX_SIZE
+            ));
+        }
+        Ok(size as usize)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+}
+
+#[cfg(test)]
+fn sample_replay() -> Replay {
+    let moves: Vec<Direction> = [
+        Direction::Left,
+        Direction::Down,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ]
+    .iter()
+    .copied()
+    .cycle()
+    .take(30)
+    .collect();
+    let config = GameConfig {
+        undo_limit: Some(3),
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config.clone(), Pcg32::new(2048)).unwrap();
+    let mut legal_moves = vec![];
+    for direction in moves {
+        if let MoveOutcome::Moved { .. } = game.make_move(direction) {
+            legal_moves.push(direction);
+        }
+    }
+    Replay::record(2048, config, &legal_moves).unwrap()
+}
+
+#[test]
+fn test_record_and_verify() {
+    let replay = sample_replay();
+    assert!(!replay.moves.is_empty());
+    assert!(replay.final_score > 0);
+    assert!(verify(&replay).is_ok());
+
+    let mut wrong_score = replay.clone();
+    wrong_score.final_score += 4;
+    assert!(verify(&wrong_score).is_err());
+
+    let mut wrong_board = replay.clone();
+    wrong_board.final_cells[0][0] += 2;
+    assert!(verify(&wrong_board).is_err());
+
+    let mut wrong_seed = replay;
+    wrong_seed.seed += 1;
+    assert!(verify(&wrong_seed).is_err());
+}
+
+#[test]
+fn test_from_game() {
+    let mut game = Game::new(GameConfig::default(), Pcg32::new(7)).unwrap();
+    game.make_move(Direction::Left);
+    game.make_move(Direction::Up);
+    game.make_move(Direction::Right);
+    game.undo().unwrap();
+
+    let replay = Replay::from_game(7, &game).unwrap();
+    assert_eq!(replay.moves, game.move_history().to_vec());
+    assert_eq!(replay.final_cells, game.cells().to_vec());
+    assert!(verify(&replay).is_ok());
+}
+
+#[test]
+fn test_text_round_trip() {
+    let replay = sample_replay();
+    let text = replay.to_text();
+    assert!(text.starts_with("2048-replay 6\nseed 2048\nrows 4\ncols 4\ntiles 2:90 4:10\n"));
+    assert_eq!(Replay::from_text(&text), Ok(replay));
+}
+
+#[test]
+fn test_invalid_text() {
+    let text = sample_replay().to_text();
+    assert!(Replay::from_text("").is_err());
+    assert!(Replay::from_text(&text.replace("2048-replay 6", "2048-replay 7")).is_err());
+    assert!(Replay::from_text(&text.replace("2048-replay 6", "2048-replay 0")).is_err());
+    assert!(Replay::from_text(&text.replace("mask none", "mask #x")).is_err());
+    assert!(Replay::from_text(&text.replace("walls none", "walls 1;2")).is_err());
+    assert!(Replay::from_text(&text.replace("movement slide", "movement hop")).is_err());
+    assert!(Replay::from_text(&text.replace("rule classic", "rule base 1")).is_err());
+    assert!(Replay::from_text(&text.replace("seed 2048\n", "")).is_err());
+    assert!(Replay::from_text(&text.replace("moves ", "moves X")).is_err());
+}
+
+#[test]
+fn test_version_1_text() {
+    let text = "2048-replay 1
+seed 2048
+size 4
+tiles 2:90 4:10
+initial-tiles 2
+win-target 2048
+endless false
+undo-limit 3
+undo-penalty 0
+moves LDRDLRLLDRDLLDRDLDRDLLDRDL
+score 148
+board 2 0 0 0/0 0 2 0/2 4 2 0/4 32 4 8
+";
+    let replay = Replay::from_text(text).unwrap();
+    assert_eq!(replay, sample_replay());
+    assert!(verify(&replay).is_ok());
+}
+
+#[test]
+fn test_version_2_text() {
+    let text = "2048-replay 2
+seed 2048
+rows 4
+cols 4
+tiles 2:90 4:10
+initial-tiles 2
+win-target 2048
+endless false
+undo-limit 3
+undo-penalty 0
+moves LDRDLRLLDRDLLDRDLDRDLLDRDL
+score 148
+board 2 0 0 0/0 0 2 0/2 4 2 0/4 32 4 8
+";
+    let replay = Replay::from_text(text).unwrap();
+    assert_eq!(replay, sample_replay());
+    assert!(verify(&replay).is_ok());
+}
+
+#[test]
+fn test_step_replay() {
+    let config = GameConfig {
+        movement: Movement::Step,
+        tile_bag: Some(vec![2, 2, 4]),
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config, Pcg32::new(5)).unwrap();
+    for _ in 0..20 {
+        let moves = game.legal_moves();
+        game.make_move(moves[game.moves() % moves.len()]);
+    }
+    let replay = Replay::from_game(5, &game).unwrap();
+    assert!(verify(&replay).is_ok());
+    let text = replay.to_text();
+    assert!(text.contains("movement step\ntile-bag 2 2 4\n"));
+    assert_eq!(Replay::from_text(&text), Ok(replay.clone()));
+    assert_eq!(Replay::from_bytes(&replay.to_bytes()), Ok(replay));
+}
+
+#[test]
+fn test_wrap_replay() {
+    let config = GameConfig {
+        movement: Movement::Wrap,
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config, Pcg32::new(3)).unwrap();
+    for _ in 0..30 {
+        if let Some(direction) = game.legal_moves().first().copied() {
+            game.make_move(direction);
+        }
+    }
+    let replay = Replay::from_game(3, &game).unwrap();
+    assert!(verify(&replay).is_ok());
+    assert!(replay.to_text().contains("movement wrap\n"));
+    assert_eq!(Replay::from_bytes(&replay.to_bytes()), Ok(replay));
+}
+
+#[test]
+fn test_rectangular_replay() {
+    let config = GameConfig {
+        rows: 3,
+        cols: 5,
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config, Pcg32::new(11)).unwrap();
+    for _ in 0..10 {
+        let moves = game.legal_moves();
+        game.make_move(moves[game.moves() % moves.len()]);
+    }
+    let replay = Replay::from_game(11, &game).unwrap();
+    assert_eq!(replay.final_cells.len(), 3);
+    assert_eq!(replay.final_cells[0].len(), 5);
+    assert!(verify(&replay).is_ok());
+    assert_eq!(Replay::from_text(&replay.to_text()), Ok(replay.clone()));
+    assert_eq!(Replay::from_bytes(&replay.to_bytes()), Ok(replay));
+}
+
+#[test]
+fn test_replay_with_walls() {
+    let config = GameConfig {
+        walls: vec![(0, 2), (3, 1)],
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config, Pcg32::new(8)).unwrap();
+    for _ in 0..15 {
+        let moves = game.legal_moves();
+        game.make_move(moves[game.moves() % moves.len()]);
+    }
+    let replay = Replay::from_game(8, &game).unwrap();
+    assert!(verify(&replay).is_ok());
+    let text = replay.to_text();
+    assert!(text.contains("walls 0,2 3,1\n"));
+    assert_eq!(Replay::from_text(&text), Ok(replay.clone()));
+    assert_eq!(Replay::from_bytes(&replay.to_bytes()), Ok(replay));
+}
+
+#[test]
+fn test_replay_with_mask() {
+    let config = GameConfig {
+        rows: 3,
+        cols: 3,
+        mask: Some(vec![
+            vec![false, true, false],
+            vec![true, true, true],
+            vec![false, true, false],
+        ]),
+        ..GameConfig::default()
+    };
+    let mut game = Game::new(config, Pcg32::new(6)).unwrap();
+    for _ in 0..8 {
+        if let Some(direction) = game.legal_moves().first().copied() {
+            game.make_move(direction);
+        }
+    }
+    let replay = Replay::from_game(6, &game).unwrap();
+    assert!(verify(&replay).is_ok());
+    let text = replay.to_text();
+    assert!(text.contains("mask .#./###/.#.\n"));
+    assert_eq!(Replay::from_text(&text), Ok(replay.clone()));
+    assert_eq!(Replay::from_bytes(&replay.to_bytes()), Ok(replay));
+}
+
+/// Records a game played with `rule`, checks that the replay round-trips and
+/// only verifies with that rule, and returns its text.
+#[cfg(test)]
+fn replay_with_rule(config: GameConfig, rule: AnyMerge) -> String {
+    let mut game = Game::with_rule(config, Pcg32::new(5), rule).unwrap();
+    for direction in crate::board::DIRECTIONS.iter().cycle().take(40) {
+        game.make_move(*direction);
+    }
+    let replay = Replay::from_game(5, &game).unwrap();
+    assert_eq!(replay.rule, rule);
+    assert!(verify(&replay).is_ok());
+    assert_eq!(Replay::from_text(&replay.to_text()), Ok(replay.clone()));
+    assert_eq!(Replay::from_bytes(&replay.to_bytes()), Ok(replay.clone()));
+
+    let classic = Replay {
+        rule: AnyMerge::Classic,
+        ..replay.clone()
+    };
+    assert!(verify(&classic).is_err());
+    replay.to_text()
+}
+
+#[test]
+fn test_replay_with_merge_rules() {
+    use crate::merge_rule::ThreesMerge;
+
+    replay_with_rule(GameConfig::threes(), AnyMerge::from(ThreesMerge));
+}
+
+#[test]
+fn test_base_merge_replay() {
+    for base in &[3, 5] {
+        let rule = BaseMerge::new(*base).unwrap();
+        let config = GameConfig::powers_of(*base).unwrap();
+        let text = replay_with_rule(config, AnyMerge::from(rule));
+        assert!(text.contains(&format!("\nrule base {}\n", base)));
+        let invalid = text.replace(&format!("rule base {}", base), "rule base 1");
+        assert!(Replay::from_text(&invalid).is_err());
+    }
+}
+
+#[test]
+fn test_fibonacci_replay() {
+    use crate::merge_rule::FibonacciMerge;
+
+    let text = replay_with_rule(GameConfig::fibonacci(), AnyMerge::from(FibonacciMerge));
+    assert!(text.contains("\nrule fibonacci\n"));
+    // Version 5 wrote no rule line, so its replays load as classic.
+    let v5 = text
+        .replace("2048-replay 6", "2048-replay 5")
+        .replace("rule fibonacci\n", "");
+    assert_eq!(
+        Replay::from_text(&v5).map(|replay| replay.rule),
+        Ok(AnyMerge::Classic)
+    );
+}
+
+#[test]
+fn test_binary_round_trip() {
+    let replay = sample_replay();
+    let bytes = replay.to_bytes();
+    assert!(bytes.starts_with(b"2KRP"));
+    assert!(bytes.len() < replay.to_text().len());
+    assert_eq!(Replay::from_bytes(&bytes), Ok(replay));
+}
+
+#[test]
+fn test_invalid_bytes() {
+    let bytes = sample_replay().to_bytes();
+    assert!(Replay::from_bytes(b"2048").is_err());
+    assert!(Replay::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    let mut trailing = bytes;
+    trailing.push(0);
+    assert!(Replay::from_bytes(&trailing).is_err());
+}
+
+#[cfg(test)]
+fn bytes_ending_with(tail: &[u64]) -> Vec<u8> {
+    let mut replay = sample_replay();
+    replay.moves.clear();
+    replay.final_score = 0;
+    replay.final_cells.clear();
+    let mut bytes = replay.to_bytes();
+    bytes.truncate(bytes.len() - 4);
+    for value in tail {
+        write_unsigned(&mut bytes, *value);
+    }
+    bytes
+}
+
+#[test]
+fn test_oversized_counts_in_bytes() {
+    assert!(Replay::from_bytes(&bytes_ending_with(&[0, 0, 4, 4])).is_err());
+    let huge_moves = bytes_ending_with(&[i64::MAX as u64, 0, 0, 0]);
+    assert_eq!(
+        Replay::from_bytes(&huge_moves),
+        Err("Replay claims 9223372036854775807 moves, but only 3 bytes are left".to_string())
+    );
+    let huge_board = bytes_ending_with(&[0, 0, 1 << 21, 1 << 21]);
+    assert_eq!(
+        Replay::from_bytes(&huge_board),
+        Err("Replay contains a board size of 2097152. Supported sizes: 2 to 16".to_string())
+    );
+}
+
+#[test]
+fn test_oversized_board_in_bytes() {
+    let mut bytes = BINARY_MAGIC.to_vec();
+    write_unsigned(&mut bytes, REPLAY_VERSION as u64);
+    bytes.extend_from_slice(&[0; 8]);
+    write_unsigned(&mut bytes, u64::MAX >> 1);
+    write_unsigned(&mut bytes, u64::MAX >> 1);
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff]);
+    assert!(Replay::from_bytes(&bytes)
+        .unwrap_err()
+        .starts_with("Replay contains a board size of"));
+}
+
+#[test]
+fn test_out_of_range_numbers_in_bytes() {
+    let header = |value: i64, probability: i64| {
+        let mut bytes = BINARY_MAGIC.to_vec();
+        write_unsigned(&mut bytes, REPLAY_VERSION as u64);
+        bytes.extend_from_slice(&[0; 8]);
+        write_unsigned(&mut bytes, 4);
+        write_unsigned(&mut bytes, 4);
+        write_unsigned(&mut bytes, 1);
+        write_signed(&mut bytes, value);
+        write_signed(&mut bytes, probability);
+        bytes
+    };
+    assert_eq!(
+        Replay::from_bytes(&header(2, 300)),
+        Err("Replay contains an out of range tile probability: 300".to_string())
+    );
+    assert_eq!(
+        Replay::from_bytes(&header(1 << 40, 100)),
+        Err("Replay contains an out of range tile value: 1099511627776".to_string())
+    );
+    // In range numbers read on to the end of the truncated header.
+    assert_eq!(
+        Replay::from_bytes(&header(2, 100)),
+        Err("Replay ended unexpectedly".to_string())
+    );
+}
+
+#[test]
+fn test_varint_round_trip() {
+    let values = [
+        0,
+        1,
+        -1,
+        63,
+        -64,
+        64,
+        300,
+        -300,
+        i32::MAX as i64,
+        i32::MIN as i64,
+    ];
+    let mut bytes = vec![];
+    for value in values.iter() {
+        write_signed(&mut bytes, *value);
+    }
+    let mut reader = ByteReader {
+        bytes: &bytes,
+        position: 0,
+    };
+    for value in values.iter() {
+        assert_eq!(reader.signed(), Ok(*value));
+    }
+}
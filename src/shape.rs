@@ -0,0 +1,51 @@
+/// A `GameConfig::mask` shaped like a cross whose arms are the middle third
+/// of the board.
+pub fn plus(size: usize) -> Vec<Vec<bool>> {
+    let arm = size / 3..size - size / 3;
+    from_fn(size, |i, j| arm.contains(&i) || arm.contains(&j))
+}
+
+/// The cells within the largest diamond that fits on the board.
+pub fn diamond(size: usize) -> Vec<Vec<bool>> {
+    let distance = |k: usize| (2 * k + 1).abs_diff(size);
+    from_fn(size, |i, j| distance(i) + distance(j) <= size)
+}
+
+/// The border of the board, a third of the board thick, around a hole.
+pub fn ring(size: usize) -> Vec<Vec<bool>> {
+    let thickness = (size / 3).max(1);
+    let hole = thickness..size.saturating_sub(thickness);
+    from_fn(size, |i, j| !(hole.contains(&i) && hole.contains(&j)))
+}
+
+fn from_fn(size: usize, active: impl Fn(usize, usize) -> bool) -> Vec<Vec<bool>> {
+    (0..size)
+        .map(|i| (0..size).map(|j| active(i, j)).collect())
+        .collect()
+}
+
+#[cfg(test)]
+fn draw(mask: &[Vec<bool>]) -> Vec<String> {
+    mask.iter()
+        .map(|row| row.iter().map(|a| if *a { '#' } else { '.' }).collect())
+        .collect()
+}
+
+#[test]
+fn test_shapes() {
+    assert_eq!(draw(&plus(4)), vec![".##.", "####", "####", ".##."]);
+    assert_eq!(
+        draw(&plus(5)),
+        vec![".###.", "#####", "#####", "#####", ".###."]
+    );
+    assert_eq!(draw(&diamond(4)), vec![".##.", "####", "####", ".##."]);
+    assert_eq!(
+        draw(&diamond(5)),
+        vec!["..#..", ".###.", "#####", ".###.", "..#.."]
+    );
+    assert_eq!(draw(&ring(4)), vec!["####", "#..#", "#..#", "####"]);
+    assert_eq!(
+        draw(&ring(6)),
+        vec!["######", "######", "##..##", "##..##", "######", "######"]
+    );
+}
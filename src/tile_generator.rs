@@ -1,31 +1,99 @@
-use crate::random::{self, RandomNumberGenerator};
+use crate::random::{RandomNumberGenerator, RngState, SnapshotGenerator};
 
 const TOTAL_PROBABILITY: i8 = 100;
 
-struct TileOption {
-    value: i32,
-    probability: i8,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileOption {
+    pub value: i32,
+    pub probability: i8,
 }
 
+#[derive(Clone)]
 pub struct TileGenerator<R: RandomNumberGenerator> {
     options: Vec<TileOption>,
     probability_intervals: Vec<f64>,
     rng: R,
+    bag: Option<TileBag>,
+}
+
+/// A shuffled set of tiles that is drawn without replacement and refilled as
+/// soon as it runs out, so the next tile is always known.
+#[derive(Clone)]
+struct TileBag {
+    tiles: Vec<i32>,
+    remaining: Vec<i32>,
+}
+
+impl TileBag {
+    fn refill<R: RandomNumberGenerator>(&mut self, rng: &mut R) {
+        self.remaining = self.tiles.clone();
+        for i in (1..self.remaining.len()).rev() {
+            let j = rng.next_in_range(0..i as i32 + 1) as usize;
+            self.remaining.swap(i, j);
+        }
+    }
 }
 
 impl<R: RandomNumberGenerator> TileGenerator<R> {
-    fn new(options: Vec<TileOption>, rng: R) -> Result<TileGenerator<R>, String> {
+    pub fn new(options: Vec<TileOption>, rng: R) -> Result<TileGenerator<R>, String> {
         let probability_intervals = create_probability_intervals(&options);
         match probability_intervals {
             Ok(v) => Ok(TileGenerator {
                 options,
                 probability_intervals: v,
                 rng,
+                bag: None,
             }),
             Err(err) => Err(err),
         }
     }
 
+    /// Draws tiles from a shuffled bag of `tiles` instead of the options.
+    pub fn with_bag(mut self, tiles: Vec<i32>) -> Result<TileGenerator<R>, String> {
+        if tiles.is_empty() || tiles.iter().any(|tile| *tile <= 0) {
+            return Err(format!(
+                "Tile bag should hold positive tiles. Actual bag: {:?}",
+                tiles
+            ));
+        }
+        let mut bag = TileBag {
+            tiles,
+            remaining: vec![],
+        };
+        bag.refill(&mut self.rng);
+        self.bag = Some(bag);
+        Ok(self)
+    }
+
+    pub fn options(&self) -> &[TileOption] {
+        &self.options
+    }
+
+    /// Tiles left in the bag, with the next tile last.
+    pub fn bag(&self) -> Option<&[i32]> {
+        self.bag.as_ref().map(|bag| &bag.remaining[..])
+    }
+
+    pub fn restore_bag(&mut self, remaining: Vec<i32>) -> Result<(), String> {
+        let bag = self.bag.as_mut().ok_or("Generator has no tile bag")?;
+        if remaining.is_empty() || remaining.iter().any(|tile| !bag.tiles.contains(tile)) {
+            return Err(format!(
+                "Tile bag {:?} cannot be drawn from {:?}",
+                remaining, bag.tiles
+            ));
+        }
+        bag.remaining = remaining;
+        Ok(())
+    }
+
+    /// The tile the next call to `next_tile` returns, if it is already known.
+    pub fn peek_tile(&self) -> Option<i32> {
+        self.bag
+            .as_ref()
+            .and_then(|bag| bag.remaining.last().copied())
+    }
+
     pub fn generate_tile(&mut self, empty_cells: &[(usize, usize)]) -> (usize, usize, i32) {
         let (i, j) = self.select_empty_cell(empty_cells);
         let tile = self.next_tile();
@@ -33,6 +101,13 @@ impl<R: RandomNumberGenerator> TileGenerator<R> {
     }
 
     pub fn next_tile(&mut self) -> i32 {
+        if let Some(bag) = &mut self.bag {
+            let tile = bag.remaining.pop().unwrap_or(bag.tiles[0]);
+            if bag.remaining.is_empty() {
+                bag.refill(&mut self.rng);
+            }
+            return tile;
+        }
         let p: f64 = self.rng.next_float();
         self.next_tile_internal(p)
     }
@@ -51,14 +126,30 @@ impl<R: RandomNumberGenerator> TileGenerator<R> {
     }
 }
 
-fn create_probability_intervals(options: &Vec<TileOption>) -> Result<Vec<f64>, String> {
+impl<R: SnapshotGenerator> TileGenerator<R> {
+    pub fn rng_state(&self) -> RngState {
+        self.rng.save_state()
+    }
+
+    pub fn restore_rng_state(&mut self, state: &RngState) -> Result<(), String> {
+        self.rng.restore_state(state)
+    }
+}
+
+fn create_probability_intervals(options: &[TileOption]) -> Result<Vec<f64>, String> {
     let mut probability_intervals = vec![];
-    let mut cummulative_probability = 0;
+    let mut cummulative_probability = 0i32;
     for option in options {
-        cummulative_probability += option.probability;
+        if !(0..=TOTAL_PROBABILITY).contains(&option.probability) {
+            return Err(format!(
+                "Probabilities should be between 0 and {}. Actual probability of {}: {}",
+                TOTAL_PROBABILITY, option.value, option.probability
+            ));
+        }
+        cummulative_probability += option.probability as i32;
         probability_intervals.push(cummulative_probability as f64 / TOTAL_PROBABILITY as f64);
     }
-    if cummulative_probability != TOTAL_PROBABILITY {
+    if cummulative_probability != TOTAL_PROBABILITY as i32 {
         return Err(format!(
             "Probabilities should sum up to {}. Actual sum: {}",
             TOTAL_PROBABILITY, cummulative_probability
@@ -88,7 +179,7 @@ fn test_random_tile_generator() {
                 probability: 40,
             },
         ],
-        random::create_simple_generator(),
+        crate::random::create_simple_generator(),
     )
     .unwrap();
 
@@ -100,7 +191,7 @@ fn test_random_tile_generator() {
 
 #[test]
 fn test_create_probability_intervals() {
-    let probability_intervals_1 = create_probability_intervals(&vec![
+    let probability_intervals_1 = create_probability_intervals(&[
         TileOption {
             value: 2,
             probability: 10,
@@ -120,7 +211,7 @@ fn test_create_probability_intervals() {
     ]);
     assert_eq!(probability_intervals_1.unwrap(), vec![0.1, 0.3, 0.6, 1.0]);
 
-    let probability_intervals_2 = create_probability_intervals(&vec![
+    let probability_intervals_2 = create_probability_intervals(&[
         TileOption {
             value: 2,
             probability: 30,
@@ -140,7 +231,7 @@ fn test_create_probability_intervals() {
     ]);
     assert_eq!(probability_intervals_2.unwrap(), vec![0.3, 0.4, 0.75, 1.0]);
 
-    let invalid_probability_intervals_1 = create_probability_intervals(&vec![
+    let invalid_probability_intervals_1 = create_probability_intervals(&[
         TileOption {
             value: 2,
             probability: 30,
@@ -152,7 +243,7 @@ fn test_create_probability_intervals() {
     ]);
     assert!(invalid_probability_intervals_1.is_err());
 
-    let invalid_probability_intervals_2 = create_probability_intervals(&vec![
+    let invalid_probability_intervals_2 = create_probability_intervals(&[
         TileOption {
             value: 2,
             probability: 30,
@@ -163,10 +254,102 @@ fn test_create_probability_intervals() {
         },
     ]);
     assert!(invalid_probability_intervals_2.is_err());
+
+    let invalid_probability_intervals_3 = create_probability_intervals(&[
+        TileOption {
+            value: 2,
+            probability: 100,
+        },
+        TileOption {
+            value: 4,
+            probability: 100,
+        },
+    ]);
+    assert_eq!(
+        invalid_probability_intervals_3,
+        Err("Probabilities should sum up to 100. Actual sum: 200".to_string())
+    );
+
+    let invalid_probability_intervals_4 = create_probability_intervals(&[
+        TileOption {
+            value: 2,
+            probability: 127,
+        },
+        TileOption {
+            value: 4,
+            probability: -27,
+        },
+    ]);
+    assert!(invalid_probability_intervals_4.is_err());
+}
+
+#[test]
+fn test_restore_rng_state() {
+    let mut random_tile_generator = TileGenerator::new(
+        vec![
+            TileOption {
+                value: 2,
+                probability: 50,
+            },
+            TileOption {
+                value: 4,
+                probability: 50,
+            },
+        ],
+        crate::random::Pcg32::new(11),
+    )
+    .unwrap();
+    let empty_cells: Vec<(usize, usize)> =
+        (0..4).flat_map(|i| (0..4).map(move |j| (i, j))).collect();
+
+    let state = random_tile_generator.rng_state();
+    let expected: Vec<(usize, usize, i32)> = (0..16)
+        .map(|_| random_tile_generator.generate_tile(&empty_cells))
+        .collect();
+    random_tile_generator.restore_rng_state(&state).unwrap();
+    let actual: Vec<(usize, usize, i32)> = (0..16)
+        .map(|_| random_tile_generator.generate_tile(&empty_cells))
+        .collect();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_tile_bag() {
+    let options = vec![TileOption {
+        value: 1,
+        probability: 100,
+    }];
+    let mut generator = TileGenerator::new(options.clone(), crate::random::Pcg32::new(5))
+        .unwrap()
+        .with_bag(vec![1, 1, 2, 2, 3, 3])
+        .unwrap();
+    for _ in 0..3 {
+        let mut drawn = vec![];
+        for _ in 0..6 {
+            let next = generator.peek_tile().unwrap();
+            assert_eq!(generator.next_tile(), next);
+            drawn.push(next);
+        }
+        drawn.sort_unstable();
+        assert_eq!(drawn, vec![1, 1, 2, 2, 3, 3]);
+    }
+
+    generator.restore_bag(vec![3, 1]).unwrap();
+    assert_eq!(generator.bag(), Some(&[3, 1][..]));
+    assert_eq!(generator.next_tile(), 1);
+    assert_eq!(generator.next_tile(), 3);
+    assert_eq!(generator.bag().unwrap().len(), 6);
+    assert!(generator.restore_bag(vec![4]).is_err());
+    assert!(generator.restore_bag(vec![]).is_err());
+
+    let plain = TileGenerator::new(options.clone(), crate::random::Pcg32::new(5)).unwrap();
+    assert_eq!(plain.peek_tile(), None);
+    assert!(plain.with_bag(vec![]).is_err());
+    let mut plain = TileGenerator::new(options, crate::random::Pcg32::new(5)).unwrap();
+    assert!(plain.restore_bag(vec![1]).is_err());
 }
 
 #[test]
 fn test_generate_tile() {
     // TODO implement test with mocks
 }
-
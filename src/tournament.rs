@@ -0,0 +1,250 @@
+use crate::agent::{Agent, GameView};
+use crate::board::Movement;
+use crate::game::{Game, GameConfig, MoveOutcome};
+use crate::random::Pcg32;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// Plays `games` games per agent. Game `i` is seeded with `seed + i`, so every
+/// agent faces the same sequence of spawns for the same moves. Agents plan on
+/// plain sliding boards, so configs with walls, a mask or another movement
+/// are rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tournament {
+    pub config: GameConfig,
+    pub games: usize,
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameResult {
+    pub seed: u64,
+    pub score: i32,
+    pub max_tile: i32,
+    pub moves: usize,
+    pub won: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub games: usize,
+    pub mean_score: f64,
+    pub median_score: f64,
+    pub max_tiles: BTreeMap<i32, usize>,
+    pub win_rate: f64,
+    pub moves_per_second: f64,
+}
+
+impl Tournament {
+    pub fn new(config: GameConfig, games: usize, seed: u64) -> Tournament {
+        Tournament {
+            config,
+            games,
+            seed,
+        }
+    }
+
+    pub fn play(&self, agent: &mut dyn Agent, seed: u64) -> Result<GameResult, String> {
+        check_config(&self.config)?;
+        let mut game = Game::new(self.config.clone(), Pcg32::new(seed))?;
+        while !game.is_over() {
+            let direction = agent.choose_move(&GameView::new(&game)?);
+            if let MoveOutcome::Illegal = game.make_move(direction) {
+                return Err(format!(
+                    "Agent chose the illegal move {:?} on move {} of game {}",
+                    direction,
+                    game.moves() + 1,
+                    seed
+                ));
+            }
+        }
+        Ok(GameResult {
+            seed,
+            score: game.score(),
+            max_tile: game.max_tile(),
+            moves: game.moves(),
+            won: !game.milestones().is_empty(),
+        })
+    }
+
+    pub fn run(&self, agent: &mut dyn Agent) -> Result<Report, String> {
+        let start = Instant::now();
+        let results = (0..self.games as u64)
+            .map(|i| self.play(agent, self.seed.wrapping_add(i)))
+            .collect::<Result<Vec<GameResult>, String>>()?;
+        Ok(Report::new(&results, start.elapsed().as_secs_f64()))
+    }
+}
+
+/// The middle value of `sorted`, or the mean of the middle two for an even
+/// count. Zero when empty.
+pub fn median(sorted: &[f64]) -> f64 {
+    match sorted.len() {
+        0 => 0.0,
+        n if n % 2 == 1 => sorted[n / 2],
+        n => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+    }
+}
+
+/// Agents look ahead with the classic `board` functions, which only slide
+/// tiles on full rectangular boards.
+pub(crate) fn check_config(config: &GameConfig) -> Result<(), String> {
+    if config.movement != Movement::Slide {
+        return Err(format!(
+            "Agents only play sliding games. Actual movement: {:?}",
+            config.movement
+        ));
+    }
+    if !config.walls.is_empty() || config.mask.is_some() {
+        return Err("Agents only play boards without walls or a mask".to_string());
+    }
+    Ok(())
+}
+
+impl Report {
+    pub fn new(results: &[GameResult], seconds: f64) -> Report {
+        let games = results.len();
+        let mut scores: Vec<i32> = results.iter().map(|result| result.score).collect();
+        scores.sort_unstable();
+        let mean_score = if games == 0 {
+            0.0
+        } else {
+            scores.iter().map(|score| *score as f64).sum::<f64>() / games as f64
+        };
+        let median_score = median(
+            &scores
+                .iter()
+                .map(|score| *score as f64)
+                .collect::<Vec<f64>>(),
+        );
+        let mut max_tiles = BTreeMap::new();
+        for result in results {
+            *max_tiles.entry(result.max_tile).or_insert(0) += 1;
+        }
+        let wins = results.iter().filter(|result| result.won).count();
+        let moves: usize = results.iter().map(|result| result.moves).sum();
+        Report {
+            games,
+            mean_score,
+            median_score,
+            max_tiles,
+            win_rate: if games == 0 {
+                0.0
+            } else {
+                wins as f64 / games as f64
+            },
+            moves_per_second: if seconds > 0.0 {
+                moves as f64 / seconds
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+#[test]
+fn test_report() {
+    let result = |score, max_tile, won| GameResult {
+        seed: 0,
+        score,
+        max_tile,
+        moves: 10,
+        won,
+    };
+    let report = Report::new(
+        &[
+            result(100, 64, false),
+            result(400, 256, true),
+            result(200, 64, false),
+            result(300, 128, true),
+        ],
+        2.0,
+    );
+    assert_eq!(report.games, 4);
+    assert_eq!(report.mean_score, 250.0);
+    assert_eq!(report.median_score, 250.0);
+    assert_eq!(
+        report.max_tiles.into_iter().collect::<Vec<(i32, usize)>>(),
+        vec![(64, 2), (128, 1), (256, 1)]
+    );
+    assert_eq!(report.win_rate, 0.5);
+    assert_eq!(report.moves_per_second, 20.0);
+
+    let empty = Report::new(&[], 0.0);
+    assert_eq!(empty.mean_score, 0.0);
+    assert_eq!(empty.median_score, 0.0);
+}
+
+#[test]
+fn test_run_is_reproducible() {
+    use crate::agent::RandomAgent;
+
+    let tournament = Tournament::new(GameConfig::default(), 5, 100);
+    let first = tournament.run(&mut RandomAgent::new(1)).unwrap();
+    let second = tournament.run(&mut RandomAgent::new(1)).unwrap();
+    assert_eq!(first.games, 5);
+    assert_eq!(first.mean_score, second.mean_score);
+    assert_eq!(first.max_tiles, second.max_tiles);
+    assert_eq!(first.max_tiles.values().sum::<usize>(), 5);
+}
+
+#[test]
+fn test_corner_beats_random() {
+    use crate::agent::{CornerAgent, RandomAgent};
+
+    let tournament = Tournament::new(GameConfig::default(), 20, 1);
+    let random = tournament.run(&mut RandomAgent::new(1)).unwrap();
+    let corner = tournament.run(&mut CornerAgent).unwrap();
+    assert!(corner.mean_score > random.mean_score);
+}
+
+#[test]
+fn test_win_rate() {
+    use crate::agent::GreedyAgent;
+
+    let config = GameConfig {
+        win_target: 16,
+        ..GameConfig::default()
+    };
+    let report = Tournament::new(config, 3, 1).run(&mut GreedyAgent).unwrap();
+    assert_eq!(report.win_rate, 1.0);
+    assert!(report.max_tiles.keys().all(|tile| *tile == 16));
+}
+
+#[test]
+fn test_illegal_move_is_an_error() {
+    use crate::board::Direction;
+
+    struct Stubborn;
+
+    impl Agent for Stubborn {
+        fn choose_move(&mut self, _: &GameView) -> Direction {
+            Direction::Up
+        }
+    }
+
+    let tournament = Tournament::new(GameConfig::default(), 1, 1);
+    assert!(tournament.run(&mut Stubborn).is_err());
+}
+
+#[test]
+fn test_unsupported_boards_are_rejected() {
+    use crate::agent::GreedyAgent;
+
+    let walled = GameConfig {
+        walls: vec![(1, 1)],
+        ..GameConfig::default()
+    };
+    let ring = GameConfig {
+        mask: Some(crate::shape::ring(4)),
+        ..GameConfig::default()
+    };
+    let wrapping = GameConfig {
+        movement: Movement::Wrap,
+        ..GameConfig::default()
+    };
+    for config in [walled, ring, wrapping].iter() {
+        let tournament = Tournament::new(config.clone(), 1, 1);
+        assert!(tournament.run(&mut GreedyAgent).is_err());
+    }
+}